@@ -0,0 +1,78 @@
+use std::sync::Arc;
+use std::f64::consts::PI;
+
+use crate::vec3::Vec3;
+use crate::distribution::Distribution2D;
+
+#[derive(Debug, Clone)]
+pub struct ApertureImage {
+    pub path: String,
+    distribution: Distribution2D,
+}
+
+impl ApertureImage {
+    pub fn load(path: &str) -> Result<ApertureImage, image::ImageError> {
+        let img = image::open(path)?.to_luma();
+        let (width, height) = img.dimensions();
+        let pixels: Vec<f64> = img.into_raw().iter().map(|p| (*p as f64) / 255.0).collect();
+
+        Ok(ApertureImage { path: path.to_string(), ..ApertureImage::from_pixels(width as usize, height as usize, &pixels) })
+    }
+
+    pub fn from_pixels(width: usize, height: usize, pixels: &[f64]) -> ApertureImage {
+        ApertureImage { path: String::new(), distribution: Distribution2D::new(pixels, width, height) }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum Aperture {
+    Circle,
+    Polygon { blades: u32, rotation: f64 },
+    Image(Arc<ApertureImage>),
+}
+
+impl Aperture {
+    pub fn sample(&self, u: (f64, f64)) -> Vec3 {
+        match self {
+            Aperture::Circle => {
+                let r = u.0.sqrt();
+                let theta = 2.0 * PI * u.1;
+
+                Vec3::new(r * theta.cos(), r * theta.sin(), 0.0)
+            }
+            Aperture::Polygon { blades, rotation } => {
+                let n = (*blades).max(3) as f64;
+                let k = (u.0 * n).floor().min(n - 1.0);
+                let su = (u.0 * n - k).sqrt();
+                let a0 = rotation.to_radians() + 2.0 * PI * k / n;
+                let a1 = a0 + 2.0 * PI / n;
+
+                Vec3::new(a0.cos(), a0.sin(), 0.0) * (su * (1.0 - u.1)) + Vec3::new(a1.cos(), a1.sin(), 0.0) * (su * u.1)
+            }
+            Aperture::Image(image) => {
+                let ((x, y), _) = image.distribution.sample(u);
+
+                Vec3::new(2.0 * x - 1.0, 1.0 - 2.0 * y, 0.0)
+            }
+        }
+    }
+}
+
+#[test]
+fn test_aperture_shapes() {
+    let hexagon = Aperture::Polygon { blades: 6, rotation: 15.0 };
+    let half = Aperture::Image(Arc::new(ApertureImage::from_pixels(2, 2, &[0.0, 1.0, 0.0, 1.0])));
+
+    for i in 0..32 {
+        for j in 0..32 {
+            let u = (((i as f64) + 0.5) / 32.0, ((j as f64) + 0.5) / 32.0);
+            let p = hexagon.sample(u);
+            let angle = p.y().atan2(p.x()) - 15.0_f64.to_radians();
+            let sector = (angle / (PI / 3.0)).rem_euclid(1.0);
+            let apothem = (PI / 6.0).cos() / ((sector - 0.5) * PI / 3.0).cos();
+
+            assert!(p.len() <= apothem + 1e-9);
+            assert!(half.sample(u).x() >= 0.0);
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use serde::{ Serialize, Deserialize };
+
+use crate::vec3::Vec3;
+use crate::ray::HitRecord;
+use crate::texture::Textures;
+use crate::texture::Texture;
+
+#[cfg(test)]
+use assert_approx_eq::assert_approx_eq;
+#[cfg(test)]
+use crate::materials::Materials;
+#[cfg(test)]
+use crate::materials::Lambertian;
+#[cfg(test)]
+use crate::texture::ImageTexture;
+#[cfg(test)]
+use crate::utils::test_hit_record;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum BumpMap {
+    Normal { map: Textures },
+    Height { map: Textures, scale: f64 },
+}
+
+impl BumpMap {
+    const DELTA: f64 = 0.0005;
+
+    pub fn shading_normal(&self, hit_record: &HitRecord, outward_norm: &Vec3) -> Vec3 {
+        let (u, v, p) = (hit_record.u, hit_record.v, hit_record.pt);
+
+        if hit_record.dpdu.near_zero() || hit_record.dpdv.near_zero() { return *outward_norm; }
+
+        let ns = match self {
+            BumpMap::Normal { map } => {
+                let c = map.value(u, v, &p);
+                let t = hit_record.dpdu.unit_vec();
+                let b = outward_norm.cross(&t);
+
+                t * (2.0 * c.x() - 1.0) + b * (2.0 * c.y() - 1.0) + *outward_norm * (2.0 * c.z() - 1.0)
+            }
+            BumpMap::Height { map, scale } => {
+                let h = map.value(u, v, &p).x() * scale;
+                let h_u = map.value(u + BumpMap::DELTA, v, &p).x() * scale;
+                let h_v = map.value(u, v + BumpMap::DELTA, &p).x() * scale;
+                let dpdu = hit_record.dpdu + *outward_norm * ((h_u - h) / BumpMap::DELTA);
+                let dpdv = hit_record.dpdv + *outward_norm * ((h_v - h) / BumpMap::DELTA);
+
+                dpdu.cross(&dpdv)
+            }
+        };
+
+        if ns.near_zero() { return *outward_norm; }
+
+        let ns = ns.unit_vec();
+
+        if ns.dot(outward_norm) < 0.0 { -ns } else { ns }
+    }
+}
+
+#[test]
+fn test_height_map_gradient() {
+    let width = 4000;
+    let ramp = (0..width).map(|i| Vec3::new((i as f64) / (width as f64), 0.0, 0.0)).collect();
+    let bump = BumpMap::Height { map: Textures::Image(ImageTexture::from_pixels(width, 1, ramp)), scale: 0.5 };
+    let mat = Materials::Lambertian(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)));
+    let mut hit_record = test_hit_record(&mat);
+
+    hit_record.u = 0.300125;
+    hit_record.v = 0.5;
+
+    let ns = bump.shading_normal(&hit_record, &Vec3::new(0.0, 0.0, 1.0));
+    let expected = Vec3::new(-0.5, 0.0, 1.0).unit_vec();
+
+    assert_approx_eq!(ns.x(), expected.x(), 1e-6);
+    assert_approx_eq!(ns.y(), expected.y(), 1e-6);
+    assert_approx_eq!(ns.z(), expected.z(), 1e-6);
+}
@@ -1,20 +1,102 @@
 use std::time::Instant;
+use std::f64::consts::PI;
+use std::sync::Arc;
 
 use crate::vec3::Vec3;
 use crate::ray::Ray;
-use crate::sphere::Sphere;
+use crate::scene::Scene;
+use crate::light_tree::LightTree;
+use crate::lens::LensSystem;
+use crate::aperture::Aperture;
+use crate::filter::Filters;
+use crate::filter::FilterSampler;
 
 use crate::utils::write_img;
 use crate::utils::clamp;
-use crate::utils::sample_sqr;
-use crate::utils::random_in_unit_disk;
-use crate::utils::ray_color;
+use crate::utils::ray_color_with_tree;
+use crate::spectrum::Rgb;
+use crate::spectrum::SampledWavelengths;
+use crate::spectrum::xyz_to_srgb;
+use crate::sampler::PixelSampler;
+use crate::sampler::Samplers;
+use crate::sampler::PIXEL_DIMENSION;
+use crate::sampler::LENS_DIMENSION;
+use crate::sampler::WAVELENGTH_DIMENSION;
 
 use rayon::iter::IntoParallelIterator;
 use rayon::iter::ParallelIterator;
 use palette::Pixel;
 use palette::Srgb;
 
+#[cfg(test)]
+use assert_approx_eq::assert_approx_eq;
+#[cfg(test)]
+use crate::sphere::Sphere;
+#[cfg(test)]
+use crate::materials::Materials;
+#[cfg(test)]
+use crate::materials::Glass;
+#[cfg(test)]
+use crate::materials::Lambertian;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderMode {
+    Rgb,
+    Spectral,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CubeFace {
+    Front,
+    Back,
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FisheyeMapping {
+    Equidistant,
+    Equisolid,
+}
+
+#[derive(Debug, Clone)]
+pub enum Projection {
+    Perspective,
+    Orthographic { view_height: f64 },
+    Equirectangular,
+    CubeMap { face: CubeFace },
+    Fisheye { mapping: FisheyeMapping, fov: f64 },
+    Realistic(Arc<LensSystem>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Convergence {
+    OffAxis,
+    ToeIn,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StereoLayout {
+    SideBySide,
+    OverUnder,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stereo {
+    pub ipd: f64,
+    pub convergence: Convergence,
+    pub layout: StereoLayout,
+}
+
+impl Stereo {
+    pub fn new(ipd: f64, convergence: Convergence, layout: StereoLayout) -> Stereo {
+        Stereo { ipd, convergence, layout }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Camera {
     pub aspect_ratio: f64,
     pub img_width: i32,
@@ -26,6 +108,15 @@ pub struct Camera {
     pub vup: Vec3,
     pub defocus_angle: f64,
     pub focus_dist: f64,
+    pub render_mode: RenderMode,
+    pub projection: Projection,
+    pub stereo: Option<Stereo>,
+    pub aperture: Aperture,
+    pub cat_eye: f64,
+    pub shift: (f64, f64),
+    pub tilt: (f64, f64),
+    pub seed: u64,
+    pub sampler: Samplers,
 
     img_height: i32,
     pixel_samples_scale: f64,
@@ -35,35 +126,62 @@ pub struct Camera {
     pixel_delta_v: Vec3,
     defocus_disk_u: Vec3,
     defocus_disk_v: Vec3,
+    focus_normal: Vec3,
+    filter_sampler: Arc<FilterSampler>,
+    u: Vec3,
+    v: Vec3,
+    w: Vec3,
 }
 
 impl Camera {
-    fn defocus_disk_sample(&self) -> Vec3 {
-        let p = random_in_unit_disk();
-        
-        self.center + (self.defocus_disk_u * p.x()) + (self.defocus_disk_v * p.y())
+    fn defocus_disk_sample(&self, s: f64, t: f64, sampler: &mut PixelSampler) -> Option<Vec3> {
+        let p = self.aperture.sample(sampler.get_2d());
+
+        if self.cat_eye > 0.0 {
+            let frame = Vec3::new((2.0 * s - 1.0) * self.aspect_ratio, 1.0 - 2.0 * t, 0.0) / (self.aspect_ratio * self.aspect_ratio + 1.0).sqrt();
+
+            if (p + frame * self.cat_eye).len() > 1.0 { return None; }
+        }
+
+        Some(self.center + (self.defocus_disk_u * p.x()) + (self.defocus_disk_v * p.y()))
     }
 
     fn render_line(
         &self,
         pixels: &mut [u8],
-        hit_world: &Vec<Sphere>,
+        scene: &Scene,
+        light_tree: &LightTree,
         i: usize,
+        eye: f64,
     ) {
         let w = self.img_width as usize;
+        let samples = self.samples_per_pixel;
+        let pixel_sampler = self.sampler.with_samples_per_pixel(samples.max(1) as u32);
     
         for j in 0..w {
             let mut color = Vec3::new(0.0, 0.0, 0.0);
-            let samples = self.samples_per_pixel;
 
-            for _ in 0..samples {
-                let ray = self.get_ray(j as i32, i as i32);
+            for k in 0..samples {
+                let mut sampler = PixelSampler::new(pixel_sampler, self.seed, (j as u32, i as u32), k as u32);
+                let Some((ray, weight)) = self.sample_ray(j as i32, i as i32, eye, &mut sampler) else { continue; };
 
-                color = color + ray_color(&ray, &hit_world, self.max_depth);
+                color = color + match self.render_mode {
+                    RenderMode::Rgb => ray_color_with_tree(&ray, scene, light_tree, self.max_depth, &Rgb, &mut sampler),
+                    RenderMode::Spectral => {
+                        sampler.start_dimension(WAVELENGTH_DIMENSION);
+
+                        let lambda = SampledWavelengths::sample_visible(sampler.get_1d());
+                        let radiance = ray_color_with_tree(&ray, scene, light_tree, self.max_depth, &lambda, &mut sampler);
+
+                        lambda.to_xyz(&radiance)
+                    }
+                } * weight;
             }
 
             color = color * self.pixel_samples_scale;
 
+            if self.render_mode == RenderMode::Spectral { color = xyz_to_srgb(&color); }
+
             let color_clamped = Vec3::new(
                 clamp(color.x()).sqrt(),
                 clamp(color.y()).sqrt(),
@@ -85,6 +203,7 @@ impl Camera {
 }
 
 impl Camera {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         aspect_ratio: f64,
         img_width: i32,
@@ -97,25 +216,7 @@ impl Camera {
         defocus_angle: f64,
         focus_dist: f64,
     ) -> Camera {
-        let img_height = (((img_width as f64) / aspect_ratio) as i32).max(1);
-        let pixel_samples_scale = 1.0 / (samples_per_pixel as f64);
-        let center = lookfrom;
-        let theta = vfov.to_radians();
-        let h = (theta / 2.0).tan();
-        let viewpoint_height = 2.0 * h * focus_dist;
-        let viewpoint_width = viewpoint_height * ((img_width as f64) / (img_height as f64));
-        let w = (lookfrom - lookat).unit_vec();
-        let u = vup.cross(&w).unit_vec();
-        let v = w.cross(&u);
-        let viewpoint_u = u * viewpoint_width;
-        let viewpoint_v = -v * viewpoint_height;
-        let pixel_delta_u = viewpoint_u / (img_width as f64);
-        let pixel_delta_v = viewpoint_v / (img_height as f64);
-        let viewpoint_upper_left = center - (w * focus_dist) - viewpoint_u / 2.0 - viewpoint_v / 2.0;
-        let pixel00_loc = viewpoint_upper_left + (pixel_delta_u + pixel_delta_v) * 0.5;
-        let defocus_radius = focus_dist * (defocus_angle / 2.0).to_radians().tan();
-        let defocus_disk_u = u * defocus_radius;
-        let defocus_disk_v = v * defocus_radius;
+        let zero = Vec3::new(0.0, 0.0, 0.0);
 
         Camera {
             aspect_ratio,
@@ -128,6 +229,65 @@ impl Camera {
             vup,
             defocus_angle,
             focus_dist,
+            render_mode: RenderMode::Rgb,
+            projection: Projection::Perspective,
+            stereo: None,
+            aperture: Aperture::Circle,
+            cat_eye: 0.0,
+            shift: (0.0, 0.0),
+            tilt: (0.0, 0.0),
+            seed: 0,
+            sampler: Samplers::default(),
+            img_height: 0,
+            pixel_samples_scale: 0.0,
+            center: lookfrom,
+            pixel00_loc: zero,
+            pixel_delta_u: zero,
+            pixel_delta_v: zero,
+            defocus_disk_u: zero,
+            defocus_disk_v: zero,
+            focus_normal: zero,
+            filter_sampler: Arc::new(FilterSampler::default()),
+            u: zero,
+            v: zero,
+            w: zero,
+        }.initialize()
+    }
+
+    fn initialize(self) -> Camera {
+        let img_height = (((self.img_width as f64) / self.aspect_ratio) as i32).max(1);
+        let pixel_samples_scale = 1.0 / (self.samples_per_pixel as f64);
+        let center = self.lookfrom;
+        let viewpoint_height = match self.projection {
+            Projection::Orthographic { view_height } => view_height,
+            _ => 2.0 * (self.vfov.to_radians() / 2.0).tan() * self.focus_dist,
+        };
+        let viewpoint_width = viewpoint_height * ((self.img_width as f64) / (img_height as f64));
+        let w = (self.lookfrom - self.lookat).unit_vec();
+        let u = self.vup.cross(&w).unit_vec();
+        let v = w.cross(&u);
+        let viewpoint_u = u * viewpoint_width;
+        let viewpoint_v = -v * viewpoint_height;
+        let pixel_delta_u = viewpoint_u / (self.img_width as f64);
+        let pixel_delta_v = viewpoint_v / (img_height as f64);
+        let shift = u * (self.shift.0 * viewpoint_width) + v * (self.shift.1 * viewpoint_height);
+        let viewpoint_upper_left = center - (w * self.focus_dist) + shift - viewpoint_u / 2.0 - viewpoint_v / 2.0;
+        let pixel00_loc = viewpoint_upper_left + (pixel_delta_u + pixel_delta_v) * 0.5;
+        let defocus_radius = self.focus_dist * (self.defocus_angle / 2.0).to_radians().tan();
+        let defocus_disk_u = u * defocus_radius;
+        let defocus_disk_v = v * defocus_radius;
+        let (tilt, swing) = (self.tilt.0.to_radians(), self.tilt.1.to_radians());
+        let focus_normal = ((w * tilt.cos() + v * tilt.sin()) * swing.cos() + u * swing.sin()).unit_vec();
+        let projection = match &self.projection {
+            Projection::Realistic(lens) => match lens.framed(self.vfov, self.aspect_ratio, self.focus_dist) {
+                Ok(framed) => Projection::Realistic(Arc::new(framed)),
+                Err(_) => Projection::Realistic(lens.clone()),
+            },
+            projection => projection.clone(),
+        };
+
+        Camera {
+            projection,
             img_height,
             pixel_samples_scale,
             center,
@@ -136,38 +296,363 @@ impl Camera {
             pixel_delta_v,
             defocus_disk_u,
             defocus_disk_v,
+            focus_normal,
+            u,
+            v,
+            w,
+            ..self
         }
     }
 
-    pub fn get_ray(&self, i: i32, j: i32) -> Ray {
-        let offset = sample_sqr();
-        let pixel_sample = self.pixel00_loc + (self.pixel_delta_u * ((i as f64) + offset.x())) + (self.pixel_delta_v * ((j as f64) + offset.y()));
-        let ray_orig = if self.defocus_angle <= 0.0 { self.center } else { self.defocus_disk_sample() };
-        let ray_dir = pixel_sample - ray_orig;
+    pub fn get_ray(&self, i: i32, j: i32, sampler: &mut PixelSampler) -> Option<Ray> {
+        self.get_stereo_ray(i, j, 0.0, sampler)
+    }
 
-        Ray::new(ray_orig, ray_dir)
+    pub fn get_stereo_ray(&self, i: i32, j: i32, eye: f64, sampler: &mut PixelSampler) -> Option<Ray> {
+        self.sample_ray(i, j, eye, sampler).map(|(ray, _)| ray)
+    }
+
+    fn sample_ray(&self, i: i32, j: i32, eye: f64, sampler: &mut PixelSampler) -> Option<(Ray, f64)> {
+        sampler.start_dimension(PIXEL_DIMENSION);
+
+        let (offset, filter_weight) = self.filter_sampler.sample(sampler.get_2d());
+        let s = ((i as f64) + 0.5 + offset.0) / (self.img_width as f64);
+        let t = ((j as f64) + 0.5 + offset.1) / (self.img_height as f64);
+        let eye_center = self.center + self.u * eye;
+
+        sampler.start_dimension(LENS_DIMENSION);
+
+        let ray = match &self.projection {
+            Projection::Perspective => {
+                let mut pixel_sample = self.pixel00_loc + (self.pixel_delta_u * ((i as f64) + offset.0)) + (self.pixel_delta_v * ((j as f64) + offset.1));
+                let ray_orig = if self.defocus_angle <= 0.0 { eye_center } else { self.defocus_disk_sample(s, t, sampler)? + self.u * eye };
+
+                if let (Some(Stereo { convergence: Convergence::ToeIn, .. }), true) = (self.stereo, eye != 0.0) {
+                    let plane_center = self.center - self.w * self.focus_dist;
+                    let rel = pixel_sample - plane_center;
+                    let forward = (plane_center - eye_center).unit_vec();
+                    let eye_u = self.vup.cross(&-forward).unit_vec();
+                    let eye_v = (-forward).cross(&eye_u);
+
+                    pixel_sample = eye_center + forward * self.focus_dist + eye_u * rel.dot(&self.u) + eye_v * rel.dot(&self.v);
+                }
+
+                if self.tilt != (0.0, 0.0) {
+                    let dir = pixel_sample - eye_center;
+                    let t = (self.center - self.w * self.focus_dist - eye_center).dot(&self.focus_normal) / dir.dot(&self.focus_normal);
+
+                    if t > 0.0 && t.is_finite() { pixel_sample = eye_center + dir * t; }
+                }
+
+                Ray::new(ray_orig, pixel_sample - ray_orig)
+            }
+            Projection::Orthographic { .. } => {
+                let pixel_sample = self.pixel00_loc + (self.pixel_delta_u * ((i as f64) + offset.0)) + (self.pixel_delta_v * ((j as f64) + offset.1));
+
+                Ray::new(pixel_sample + self.w * self.focus_dist + self.u * eye, -self.w)
+            }
+            Projection::Equirectangular => {
+                let phi = (s - 0.5) * 2.0 * PI;
+                let theta = (0.5 - t) * PI;
+                let dir = -self.w * (theta.cos() * phi.cos()) + self.u * (theta.cos() * phi.sin()) + self.v * theta.sin();
+                let tangent = self.u * phi.cos() + self.w * phi.sin();
+
+                Ray::new(self.center + tangent * eye, dir)
+            }
+            Projection::CubeMap { face } => {
+                let (forward, right, up) = match face {
+                    CubeFace::Front => (-self.w, self.u, self.v),
+                    CubeFace::Back => (self.w, -self.u, self.v),
+                    CubeFace::Right => (self.u, self.w, self.v),
+                    CubeFace::Left => (-self.u, -self.w, self.v),
+                    CubeFace::Up => (self.v, self.u, self.w),
+                    CubeFace::Down => (-self.v, self.u, -self.w),
+                };
+
+                Ray::new(eye_center, forward + right * (2.0 * s - 1.0) + up * (1.0 - 2.0 * t))
+            }
+            Projection::Fisheye { mapping, fov } => {
+                let min_dim = self.img_width.min(self.img_height) as f64;
+                let x = (2.0 * s - 1.0) * (self.img_width as f64) / min_dim;
+                let y = (1.0 - 2.0 * t) * (self.img_height as f64) / min_dim;
+                let r = (x * x + y * y).sqrt();
+
+                if r > 1.0 { return None; }
+
+                let half_fov = fov.to_radians() / 2.0;
+                let theta = match mapping {
+                    FisheyeMapping::Equidistant => r * half_fov,
+                    FisheyeMapping::Equisolid => 2.0 * (r * (half_fov / 2.0).sin()).clamp(-1.0, 1.0).asin(),
+                };
+                let (cos_phi, sin_phi) = if r > 0.0 { (x / r, y / r) } else { (1.0, 0.0) };
+                let dir = -self.w * theta.cos() + (self.u * cos_phi + self.v * sin_phi) * theta.sin();
+
+                Ray::new(eye_center, dir)
+            }
+            Projection::Realistic(lens) => {
+                let (ray, weight) = lens.sample_ray(s, t, self.aspect_ratio, sampler.get_2d())?;
+                let to_world = |p: &Vec3| self.u * p.x() + self.v * p.y() - self.w * p.z();
+
+                return Some((Ray::new(eye_center + to_world(&ray.orig), to_world(&ray.dir)), weight * filter_weight));
+            }
+        };
+
+        Some((ray, filter_weight))
+    }
+
+    pub fn with_render_mode(self, render_mode: RenderMode) -> Camera {
+        Camera { render_mode, ..self }
+    }
+
+    pub fn with_projection(self, projection: Projection) -> Camera {
+        Camera { projection, ..self }.initialize()
+    }
+
+    pub fn with_stereo(self, stereo: Stereo) -> Camera {
+        Camera { stereo: Some(stereo), ..self }
+    }
+
+    pub fn with_aperture(self, aperture: Aperture) -> Camera {
+        Camera { aperture, ..self }
+    }
+
+    pub fn with_cat_eye(self, cat_eye: f64) -> Camera {
+        Camera { cat_eye, ..self }
+    }
+
+    pub fn with_seed(self, seed: u64) -> Camera {
+        Camera { seed, ..self }
+    }
+
+    pub fn with_sampler(self, sampler: Samplers) -> Camera {
+        Camera { sampler, ..self }
+    }
+
+    pub fn with_filter(self, filter: Filters) -> Camera {
+        Camera { filter_sampler: Arc::new(FilterSampler::new(filter)), ..self }
+    }
+
+    pub fn with_shift(self, shift: (f64, f64)) -> Camera {
+        Camera { shift, ..self }.initialize()
+    }
+
+    pub fn with_tilt(self, tilt: (f64, f64)) -> Camera {
+        Camera { tilt, ..self }.initialize()
     }
 }
 
 impl Camera {
-    pub fn render(&self, hit_world: &Vec<Sphere>) {
-        let mut pixels = vec![0; (self.img_width * self.img_height * 3) as usize];
-        let bands: Vec<(usize, &mut [u8])> = pixels.chunks_mut((self.img_width * 3) as usize).enumerate().collect();
+    pub fn render(&self, scene: &Scene) {
+        let (width, height) = (self.img_width as usize, self.img_height as usize);
+        let (out_width, out_height) = match self.stereo {
+            Some(Stereo { layout: StereoLayout::SideBySide, .. }) => (2 * width, height),
+            Some(Stereo { layout: StereoLayout::OverUnder, .. }) => (width, 2 * height),
+            None => (width, height),
+        };
+        let mut pixels = vec![0; out_width * out_height * 3];
+        let bands: Vec<(usize, &mut [u8])> = pixels.chunks_mut(out_width * 3).enumerate().collect();
+        let light_tree = scene.light_tree();
 
         let start = Instant::now();
 
         bands.into_par_iter().for_each(
             |(i, band)| {
-                self.render_line(
-                    band,
-                    &hit_world,
-                    i,
-                );
+                match self.stereo {
+                    Some(Stereo { ipd, layout: StereoLayout::SideBySide, .. }) => {
+                        let (left, right) = band.split_at_mut(width * 3);
+
+                        self.render_line(left, scene, &light_tree, i, -ipd / 2.0);
+                        self.render_line(right, scene, &light_tree, i, ipd / 2.0);
+                    }
+                    Some(Stereo { ipd, layout: StereoLayout::OverUnder, .. }) => {
+                        let eye = if i < height { -ipd / 2.0 } else { ipd / 2.0 };
+
+                        self.render_line(band, scene, &light_tree, i % height, eye);
+                    }
+                    None => {
+                        self.render_line(
+                            band,
+                            scene,
+                            &light_tree,
+                            i,
+                            0.0,
+                        );
+                    }
+                }
             }
         );
 
         println!("Render time: {}s.", start.elapsed().as_secs());
 
-        write_img("rust_raytracer.png", &pixels, (self.img_width as usize, self.img_height as usize)).expect("err writing img");
+        write_img("rust_raytracer.png", &pixels, (out_width, out_height)).expect("err writing img");
     }
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_orthographic() {
+    let mut sampler = PixelSampler::new(Samplers::default(), 0, (0, 0), 0);
+    let cam = Camera::new(
+        1.0,
+        10,
+        1,
+        1,
+        90.0,
+        Vec3::new(0.0, 0.0, 5.0),
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        0.0,
+        5.0,
+    ).with_projection(Projection::Orthographic { view_height: 2.0 });
+    let corner = cam.get_ray(0, 0, &mut sampler).unwrap();
+    let opposite = cam.get_ray(9, 9, &mut sampler).unwrap();
+
+    assert_approx_eq!(corner.dir.unit_vec().z(), -1.0);
+    assert_approx_eq!(opposite.dir.unit_vec().z(), -1.0);
+    assert_approx_eq!(corner.orig.z(), 5.0);
+    assert!(corner.orig.x() < -0.7 && corner.orig.y() > 0.7);
+    assert!(opposite.orig.x() > 0.7 && opposite.orig.y() < -0.7);
+}
+
+#[test]
+fn test_panoramic() {
+    let mut sampler = PixelSampler::new(Samplers::default(), 0, (0, 0), 0);
+    let cam = Camera::new(
+        2.0,
+        200,
+        1,
+        1,
+        90.0,
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, -1.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        0.0,
+        1.0,
+    );
+    let equirect = cam.with_projection(Projection::Equirectangular);
+    let right = equirect.get_ray(150, 50, &mut sampler).unwrap().dir.unit_vec();
+    let up = equirect.get_ray(100, 0, &mut sampler).unwrap().dir.unit_vec();
+
+    assert_approx_eq!(right.x(), 1.0, 0.05);
+    assert_approx_eq!(up.y(), 1.0, 0.05);
+
+    let fisheye = equirect.with_projection(Projection::Fisheye { mapping: FisheyeMapping::Equisolid, fov: 180.0 });
+    let edge = fisheye.get_ray(149, 50, &mut sampler).unwrap().dir.unit_vec();
+
+    assert!(fisheye.get_ray(0, 0, &mut sampler).is_none());
+    assert_approx_eq!(fisheye.get_ray(100, 50, &mut sampler).unwrap().dir.unit_vec().z(), -1.0, 0.01);
+    assert!(edge.z().abs() < 0.05 && edge.x() > 0.95);
+}
+
+#[test]
+fn test_stereo_convergence() {
+    let mut sampler = PixelSampler::new(Samplers::default(), 0, (0, 0), 0);
+    let cam = Camera::new(
+        1.0,
+        11,
+        1,
+        1,
+        60.0,
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, -1.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        0.0,
+        4.0,
+    );
+
+    for convergence in [Convergence::OffAxis, Convergence::ToeIn] {
+        let stereo = cam.clone().with_stereo(Stereo::new(0.064, convergence, StereoLayout::SideBySide));
+        let n = 500;
+        let mut disparity = 0.0;
+
+        for k in 0..n {
+            let mut sampler = PixelSampler::new(Samplers::default(), 0, (5, 5), k);
+            let left = stereo.get_stereo_ray(5, 5, -0.032, &mut sampler).unwrap();
+            let right = stereo.get_stereo_ray(5, 5, 0.032, &mut sampler).unwrap();
+
+            assert_approx_eq!(left.orig.x(), -0.032);
+            assert_approx_eq!(right.orig.x(), 0.032);
+
+            disparity += (left.at(4.0 / -left.dir.z()).x() - right.at(4.0 / -right.dir.z()).x()) / (n as f64);
+        }
+
+        assert_approx_eq!(disparity, 0.0, 0.03);
+    }
+
+    let ods = cam.with_projection(Projection::Equirectangular).with_stereo(Stereo::new(0.064, Convergence::OffAxis, StereoLayout::OverUnder));
+    let ray = ods.get_stereo_ray(8, 5, 0.032, &mut sampler).unwrap();
+
+    assert_approx_eq!(ray.orig.len(), 0.032);
+    assert_approx_eq!(ray.orig.dot(&ray.dir), 0.0);
+}
+
+#[test]
+fn test_shift_and_tilt() {
+    let cam = Camera::new(
+        1.0,
+        101,
+        1,
+        1,
+        90.0,
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, -1.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        40.0,
+        2.0,
+    );
+    let shifted = cam.clone().with_shift((0.0, 0.25));
+    let mut rise = 0.0;
+
+    for k in 0..200 {
+        let mut sampler = PixelSampler::new(Samplers::default(), 0, (50, 50), k);
+        let dir = shifted.get_ray(50, 50, &mut sampler).unwrap().dir;
+
+        rise += dir.y() / -dir.z() / 200.0;
+    }
+
+    assert_approx_eq!(rise, 0.5, 0.02);
+
+    let tilted = cam.with_tilt((30.0, 0.0));
+    let normal = Vec3::new(0.0, 30.0_f64.to_radians().sin(), 30.0_f64.to_radians().cos());
+    let spread = |plane: &Vec3| {
+        let points: Vec<Vec3> = (0..200).map(|k| {
+            let ray = tilted.get_ray(50, 20, &mut PixelSampler::new(Samplers::default(), 0, (50, 20), k)).unwrap();
+
+            ray.at((Vec3::new(0.0, 0.0, -2.0) - ray.orig).dot(plane) / ray.dir.dot(plane))
+        }).collect();
+        let mean = points.iter().fold(Vec3::new(0.0, 0.0, 0.0), |m, p| m + *p) / 200.0;
+
+        points.iter().map(|p| (*p - mean).len()).fold(0.0, f64::max)
+    };
+
+    assert!(spread(&normal) < 0.5 * spread(&Vec3::new(0.0, 0.0, 1.0)));
+}
+
+#[test]
+fn test_seeded_render() {
+    let scene = Scene::new(vec![
+        Sphere::new(Vec3::new(0.0, 0.0, -1.0), 0.5, Materials::Glass(Glass::new(1.5))),
+        Sphere::new(Vec3::new(0.0, -100.5, -1.0), 100.0, Materials::Lambertian(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)))),
+    ]);
+    let cam = Camera::new(
+        1.0,
+        16,
+        4,
+        8,
+        90.0,
+        Vec3::new(0.0, 0.0, 0.0),
+        Vec3::new(0.0, 0.0, -1.0),
+        Vec3::new(0.0, 1.0, 0.0),
+        2.0,
+        1.0,
+    ).with_seed(7);
+    let render = |cam: &Camera| {
+        let mut pixels = vec![0; 16 * 3];
+
+        cam.render_line(&mut pixels, &scene, &scene.light_tree(), 8, 0.0);
+
+        pixels
+    };
+
+    assert_eq!(render(&cam), render(&cam));
+    assert_ne!(render(&cam), render(&cam.clone().with_seed(8)));
+}
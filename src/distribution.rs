@@ -0,0 +1,107 @@
+#[cfg(test)]
+use assert_approx_eq::assert_approx_eq;
+
+#[derive(Debug, Clone)]
+pub struct Distribution1D {
+    func: Vec<f64>,
+    cdf: Vec<f64>,
+    integral: f64,
+}
+
+impl Distribution1D {
+    pub fn new(func: Vec<f64>) -> Distribution1D {
+        let n = func.len();
+        let mut cdf = vec![0.0; n + 1];
+
+        for i in 1..=n {
+            cdf[i] = cdf[i - 1] + func[i - 1].abs() / (n as f64);
+        }
+
+        let integral = cdf[n];
+
+        for (i, c) in cdf.iter_mut().enumerate().skip(1) {
+            *c = if integral > 0.0 { *c / integral } else { (i as f64) / (n as f64) };
+        }
+
+        Distribution1D { func, cdf, integral }
+    }
+
+    pub fn count(&self) -> usize {
+        self.func.len()
+    }
+
+    pub fn integral(&self) -> f64 {
+        self.integral
+    }
+
+    pub fn sample(&self, u: f64) -> (f64, f64, usize) {
+        let n = self.count();
+        let offset = self.cdf.partition_point(|c| *c <= u).clamp(1, n) - 1;
+        let width = self.cdf[offset + 1] - self.cdf[offset];
+        let du = if width > 0.0 { (u - self.cdf[offset]) / width } else { 0.0 };
+
+        (((offset as f64) + du) / (n as f64), self.pdf_at(offset), offset)
+    }
+
+    pub fn pdf(&self, x: f64) -> f64 {
+        let n = self.count();
+
+        self.pdf_at(((x * n as f64) as usize).min(n - 1))
+    }
+
+    fn pdf_at(&self, offset: usize) -> f64 {
+        if self.integral > 0.0 { self.func[offset].abs() / self.integral } else { 1.0 }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Distribution2D {
+    conditional: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    pub fn new(func: &[f64], nu: usize, nv: usize) -> Distribution2D {
+        let conditional: Vec<Distribution1D> = func.chunks(nu).take(nv).map(
+            |row| Distribution1D::new(row.to_vec())
+        ).collect();
+        let marginal = Distribution1D::new(conditional.iter().map(|d| d.integral()).collect());
+
+        Distribution2D { conditional, marginal }
+    }
+
+    pub fn integral(&self) -> f64 {
+        self.marginal.integral()
+    }
+
+    pub fn sample(&self, u: (f64, f64)) -> ((f64, f64), f64) {
+        let (v, pdf_v, row) = self.marginal.sample(u.1);
+        let (u, pdf_u, _) = self.conditional[row].sample(u.0);
+
+        ((u, v), pdf_u * pdf_v)
+    }
+
+    pub fn pdf(&self, uv: (f64, f64)) -> f64 {
+        let nv = self.marginal.count();
+        let row = ((uv.1 * nv as f64) as usize).min(nv - 1);
+
+        self.conditional[row].pdf(uv.0) * self.marginal.pdf(uv.1)
+    }
+}
+
+#[test]
+fn test_distribution_2d() {
+    let dist = Distribution2D::new(&[0.0, 1.0, 2.0, 1.0, 0.0, 4.0], 3, 2);
+
+    assert_approx_eq!(dist.integral(), 4.0 / 3.0);
+
+    for i in 0..100 {
+        let u = ((i as f64) + 0.5) / 100.0;
+        let (uv, pdf) = dist.sample((u, 1.0 - u));
+
+        assert!(pdf > 0.0);
+        assert_approx_eq!(dist.pdf(uv), pdf);
+    }
+
+    assert_approx_eq!(dist.pdf((0.5, 0.9)), 0.0);
+}
@@ -0,0 +1,280 @@
+use std::f64::consts::PI;
+
+use crate::distribution::Distribution2D;
+
+#[cfg(test)]
+use assert_approx_eq::assert_approx_eq;
+
+const SAMPLES_PER_UNIT: f64 = 32.0;
+
+pub trait Filter {
+    fn radius(&self) -> f64;
+
+    fn evaluate(&self, x: f64, y: f64) -> f64;
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BoxFilter {
+    pub radius: f64,
+}
+
+impl BoxFilter {
+    pub fn new(radius: f64) -> BoxFilter {
+        BoxFilter { radius }
+    }
+}
+
+impl Filter for BoxFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        if x.abs() <= self.radius && y.abs() <= self.radius { 1.0 } else { 0.0 }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TentFilter {
+    pub radius: f64,
+}
+
+impl TentFilter {
+    pub fn new(radius: f64) -> TentFilter {
+        TentFilter { radius }
+    }
+}
+
+impl Filter for TentFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        (self.radius - x.abs()).max(0.0) * (self.radius - y.abs()).max(0.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct GaussianFilter {
+    pub radius: f64,
+    pub sigma: f64,
+}
+
+impl GaussianFilter {
+    pub fn new(radius: f64, sigma: f64) -> GaussianFilter {
+        GaussianFilter { radius, sigma }
+    }
+
+    fn gaussian(&self, x: f64) -> f64 {
+        (-x * x / (2.0 * self.sigma * self.sigma)).exp()
+    }
+}
+
+impl Filter for GaussianFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        let edge = self.gaussian(self.radius);
+
+        (self.gaussian(x) - edge).max(0.0) * (self.gaussian(y) - edge).max(0.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MitchellFilter {
+    pub radius: f64,
+    pub b: f64,
+    pub c: f64,
+}
+
+impl MitchellFilter {
+    pub fn new(radius: f64, b: f64, c: f64) -> MitchellFilter {
+        MitchellFilter { radius, b, c }
+    }
+
+    fn mitchell(&self, x: f64) -> f64 {
+        let (b, c) = (self.b, self.c);
+        let x = x.abs();
+
+        if x <= 1.0 {
+            ((12.0 - 9.0 * b - 6.0 * c) * x * x * x + (-18.0 + 12.0 * b + 6.0 * c) * x * x + (6.0 - 2.0 * b)) / 6.0
+        } else if x <= 2.0 {
+            ((-b - 6.0 * c) * x * x * x + (6.0 * b + 30.0 * c) * x * x + (-12.0 * b - 48.0 * c) * x + (8.0 * b + 24.0 * c)) / 6.0
+        } else {
+            0.0
+        }
+    }
+}
+
+impl Filter for MitchellFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        self.mitchell(2.0 * x / self.radius) * self.mitchell(2.0 * y / self.radius)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LanczosFilter {
+    pub radius: f64,
+    pub tau: f64,
+}
+
+impl LanczosFilter {
+    pub fn new(radius: f64, tau: f64) -> LanczosFilter {
+        LanczosFilter { radius, tau }
+    }
+
+    fn windowed_sinc(&self, x: f64) -> f64 {
+        if x.abs() > self.radius { return 0.0; }
+
+        sinc(x) * sinc(x / self.tau)
+    }
+}
+
+impl Filter for LanczosFilter {
+    fn radius(&self) -> f64 {
+        self.radius
+    }
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        self.windowed_sinc(x) * self.windowed_sinc(y)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Filters {
+    Box(BoxFilter),
+    Tent(TentFilter),
+    Gaussian(GaussianFilter),
+    Mitchell(MitchellFilter),
+    Lanczos(LanczosFilter),
+}
+
+impl Default for Filters {
+    fn default() -> Filters {
+        Filters::Box(BoxFilter::new(0.5))
+    }
+}
+
+impl Filter for Filters {
+    fn radius(&self) -> f64 {
+        match self {
+            Filters::Box(f) => { f.radius() }
+            Filters::Tent(f) => { f.radius() }
+            Filters::Gaussian(f) => { f.radius() }
+            Filters::Mitchell(f) => { f.radius() }
+            Filters::Lanczos(f) => { f.radius() }
+        }
+    }
+
+    fn evaluate(&self, x: f64, y: f64) -> f64 {
+        match self {
+            Filters::Box(f) => { f.evaluate(x, y) }
+            Filters::Tent(f) => { f.evaluate(x, y) }
+            Filters::Gaussian(f) => { f.evaluate(x, y) }
+            Filters::Mitchell(f) => { f.evaluate(x, y) }
+            Filters::Lanczos(f) => { f.evaluate(x, y) }
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FilterSampler {
+    pub filter: Filters,
+    resolution: usize,
+    values: Vec<f64>,
+    integral: f64,
+    distribution: Distribution2D,
+}
+
+impl FilterSampler {
+    pub fn new(filter: Filters) -> FilterSampler {
+        let radius = filter.radius();
+        let resolution = ((2.0 * radius * SAMPLES_PER_UNIT).ceil() as usize).max(1);
+        let cell = 2.0 * radius / (resolution as f64);
+        let values: Vec<f64> = (0..resolution * resolution).map(|i| {
+            let x = -radius + ((i % resolution) as f64 + 0.5) * cell;
+            let y = -radius + ((i / resolution) as f64 + 0.5) * cell;
+
+            filter.evaluate(x, y)
+        }).collect();
+        let integral = values.iter().sum::<f64>() * cell * cell;
+        let distribution = Distribution2D::new(&values.iter().map(|v| v.abs()).collect::<Vec<f64>>(), resolution, resolution);
+
+        FilterSampler { filter, resolution, values, integral, distribution }
+    }
+
+    pub fn sample(&self, u: (f64, f64)) -> ((f64, f64), f64) {
+        let radius = self.filter.radius();
+
+        if let Filters::Box(_) = self.filter { return (((2.0 * u.0 - 1.0) * radius, (2.0 * u.1 - 1.0) * radius), 1.0); }
+
+        let ((x, y), pdf) = self.distribution.sample(u);
+        let i = ((x * self.resolution as f64) as usize).min(self.resolution - 1);
+        let j = ((y * self.resolution as f64) as usize).min(self.resolution - 1);
+        let pdf = pdf / (4.0 * radius * radius);
+
+        if pdf <= 0.0 || self.integral <= 0.0 { return ((0.0, 0.0), 0.0); }
+
+        (((2.0 * x - 1.0) * radius, (2.0 * y - 1.0) * radius), self.values[j * self.resolution + i] / (pdf * self.integral))
+    }
+}
+
+impl Default for FilterSampler {
+    fn default() -> FilterSampler {
+        FilterSampler::new(Filters::default())
+    }
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-5 { return 1.0; }
+
+    (PI * x).sin() / (PI * x)
+}
+
+#[test]
+fn test_filter_importance_sampling() {
+    let filters = [
+        Filters::Box(BoxFilter::new(0.5)),
+        Filters::Tent(TentFilter::new(1.0)),
+        Filters::Gaussian(GaussianFilter::new(1.5, 0.5)),
+        Filters::Mitchell(MitchellFilter::new(2.0, 1.0 / 3.0, 1.0 / 3.0)),
+        Filters::Lanczos(LanczosFilter::new(3.0, 3.0)),
+    ];
+    let g = |x: f64, y: f64| 1.0 + x * x + 0.5 * y;
+
+    for filter in filters {
+        let sampler = FilterSampler::new(filter);
+        let r = filter.radius();
+        let n = 256;
+        let (mut reference, mut norm) = (0.0, 0.0);
+        let (mut estimate, mut weights) = (0.0, 0.0);
+
+        for i in 0..n * n {
+            let x = -r + 2.0 * r * ((i % n) as f64 + 0.5) / (n as f64);
+            let y = -r + 2.0 * r * ((i / n) as f64 + 0.5) / (n as f64);
+
+            reference += filter.evaluate(x, y) * g(x, y);
+            norm += filter.evaluate(x, y);
+        }
+
+        for i in 0..512 * 512 {
+            let u = (((i % 512) as f64 + 0.5) / 512.0, ((i / 512) as f64 + 0.5) / 512.0);
+            let ((x, y), weight) = sampler.sample(u);
+
+            assert!(x.abs() <= r && y.abs() <= r);
+
+            estimate += weight * g(x, y);
+            weights += weight;
+        }
+
+        assert_approx_eq!(weights / (512.0 * 512.0), 1.0, 0.02);
+        assert_approx_eq!(estimate / weights, reference / norm, 0.02);
+    }
+}
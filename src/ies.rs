@@ -0,0 +1,211 @@
+use std::fs;
+use std::io::Error;
+use std::io::ErrorKind;
+use serde::{ Serialize, Deserialize };
+
+use crate::vec3::Vec3;
+
+#[cfg(test)]
+use assert_approx_eq::assert_approx_eq;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct IesOrientation {
+    pub nadir: Vec3,
+    pub zero_azimuth: Vec3,
+}
+
+impl Default for IesOrientation {
+    fn default() -> IesOrientation {
+        IesOrientation { nadir: Vec3::new(0.0, -1.0, 0.0), zero_azimuth: Vec3::new(1.0, 0.0, 0.0) }
+    }
+}
+
+impl IesOrientation {
+    pub fn new(nadir: Vec3, zero_azimuth: Vec3) -> IesOrientation {
+        IesOrientation { nadir, zero_azimuth }
+    }
+
+    fn frame(&self) -> (Vec3, Vec3, Vec3) {
+        let nadir = self.nadir.unit_vec();
+        let r0 = self.zero_azimuth - nadir * self.zero_azimuth.dot(&nadir);
+
+        // A zero azimuth axis along the nadir falls back to any perpendicular axis.
+        let r0 = if r0.len_sqr() > 1e-12 {
+            r0.unit_vec()
+        } else {
+            let reference = if nadir.x().abs() < 0.9 { Vec3::new(1.0, 0.0, 0.0) } else { Vec3::new(0.0, 0.0, 1.0) };
+
+            (reference - nadir * reference.dot(&nadir)).unit_vec()
+        };
+
+        (nadir, r0, nadir.cross(&r0))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct IesProfile {
+    pub path: String,
+    vertical: Vec<f64>,
+    horizontal: Vec<f64>,
+    candela: Vec<Vec<f64>>,
+    max_candela: f64,
+}
+
+impl IesProfile {
+    pub fn load(path: &str) -> Result<IesProfile, Error> {
+        let mut profile = IesProfile::parse(&fs::read_to_string(path)?)?;
+
+        profile.path = path.to_string();
+
+        Ok(profile)
+    }
+
+    pub fn parse(text: &str) -> Result<IesProfile, Error> {
+        let invalid = |msg: &str| Error::new(ErrorKind::InvalidData, format!("invalid ies data: {}", msg));
+        let mut lines = text.lines();
+        let tilt = loop {
+            let Some(line) = lines.next() else { return Err(invalid("missing TILT line")); };
+            let line = line.trim();
+
+            if let Some(tilt) = line.strip_prefix("TILT=") { break tilt.trim().to_string(); }
+        };
+        let mut tokens = lines.flat_map(|l| l.split(|c: char| c.is_whitespace() || c == ',')).filter(|t| !t.is_empty());
+        let mut next = || -> Result<f64, Error> {
+            let token = tokens.next().ok_or_else(|| invalid("unexpected end of data"))?;
+
+            token.parse::<f64>().map_err(|_| invalid(token))
+        };
+
+        if tilt == "INCLUDE" {
+            next()?;
+
+            let n_tilt = next()? as usize;
+
+            for _ in 0..2 * n_tilt {
+                next()?;
+            }
+        }
+
+        let _num_lamps = next()?;
+        let _lumens_per_lamp = next()?;
+        let multiplier = next()?;
+        let n_vertical = next()? as usize;
+        let n_horizontal = next()? as usize;
+        let photometric_type = next()? as i32;
+
+        if photometric_type != 1 { return Err(invalid("only type C photometry is supported")); }
+        if n_vertical == 0 || n_horizontal == 0 { return Err(invalid("empty angle grid")); }
+
+        for _ in 0..4 {
+            next()?;
+        }
+
+        let ballast_factor = next()?;
+
+        next()?;
+        next()?;
+
+        let vertical = (0..n_vertical).map(|_| next()).collect::<Result<Vec<f64>, Error>>()?;
+        let horizontal = (0..n_horizontal).map(|_| next()).collect::<Result<Vec<f64>, Error>>()?;
+        let candela = (0..n_horizontal).map(
+            |_| (0..n_vertical).map(|_| next().map(|c| c * multiplier * ballast_factor)).collect::<Result<Vec<f64>, Error>>()
+        ).collect::<Result<Vec<Vec<f64>>, Error>>()?;
+        let max_candela = candela.iter().flatten().fold(0.0_f64, |m, c| m.max(*c));
+
+        Ok(IesProfile { path: String::new(), vertical, horizontal, candela, max_candela })
+    }
+
+    pub fn candela(&self, dir: &Vec3, orientation: &IesOrientation) -> f64 {
+        let (nadir, r0, r1) = orientation.frame();
+        let dir = dir.unit_vec();
+        let theta = dir.dot(&nadir).clamp(-1.0, 1.0).acos().to_degrees();
+        let phi = dir.dot(&r1).atan2(dir.dot(&r0)).to_degrees().rem_euclid(360.0);
+        let last = *self.horizontal.last().unwrap();
+        let phi = if self.horizontal.len() == 1 {
+            self.horizontal[0]
+        } else if last <= 90.0 {
+            let phi = if phi > 180.0 { 360.0 - phi } else { phi };
+
+            if phi > 90.0 { 180.0 - phi } else { phi }
+        } else if last <= 180.0 {
+            if phi > 180.0 { 360.0 - phi } else { phi }
+        } else {
+            phi
+        };
+
+        if theta < self.vertical[0] || theta > *self.vertical.last().unwrap() { return 0.0; }
+
+        let (h0, h1, th) = bracket(&self.horizontal, phi);
+        let (v0, v1, tv) = bracket(&self.vertical, theta);
+        let c0 = self.candela[h0][v0] * (1.0 - tv) + self.candela[h0][v1] * tv;
+        let c1 = self.candela[h1][v0] * (1.0 - tv) + self.candela[h1][v1] * tv;
+
+        c0 * (1.0 - th) + c1 * th
+    }
+
+    pub fn scale(&self, dir: &Vec3, orientation: &IesOrientation) -> f64 {
+        if self.max_candela <= 0.0 { return 0.0; }
+
+        self.candela(dir, orientation) / self.max_candela
+    }
+}
+
+fn bracket(angles: &[f64], x: f64) -> (usize, usize, f64) {
+    let n = angles.len();
+
+    if n == 1 || x <= angles[0] { return (0, 0, 0.0); }
+    if x >= angles[n - 1] { return (n - 1, n - 1, 0.0); }
+
+    let i = angles.partition_point(|a| *a <= x) - 1;
+    let width = angles[i + 1] - angles[i];
+
+    (i, i + 1, if width > 0.0 { (x - angles[i]) / width } else { 0.0 })
+}
+
+impl TryFrom<String> for IesProfile {
+    type Error = String;
+
+    fn try_from(path: String) -> Result<IesProfile, String> {
+        IesProfile::load(&path).map_err(|e| format!("err loading {}: {}", path, e))
+    }
+}
+
+impl From<IesProfile> for String {
+    fn from(profile: IesProfile) -> String {
+        profile.path
+    }
+}
+
+#[test]
+fn test_ies_lm63_1995() {
+    let text = "IESNA:LM-63-1995\n[TEST] sample\n[MANUFAC] none\nTILT=NONE\n\
+        1 1000 2.0 3 3 1 1 0 0 0\n\
+        1.0 1.0 100\n\
+        0 45 90\n\
+        0 90 180\n\
+        100 50 0\n\
+        100 40 0\n\
+        100 30 0\n";
+    let profile = IesProfile::parse(text).unwrap();
+    let nadir = Vec3::new(0.0, -1.0, 0.0);
+    let orientation = IesOrientation::default();
+
+    assert_approx_eq!(profile.candela(&nadir, &orientation), 200.0);
+    assert_approx_eq!(profile.candela(&Vec3::new(1.0, -1.0, 0.0), &orientation), 100.0);
+    assert_approx_eq!(profile.candela(&Vec3::new(-1.0, -1.0, 0.0), &orientation), 60.0);
+    assert_approx_eq!(profile.candela(&Vec3::new(0.0, 0.0, 1.0), &orientation), profile.candela(&Vec3::new(0.0, 0.0, -1.0), &orientation));
+    assert_approx_eq!(profile.candela(&Vec3::new(0.0, 1.0, 0.0), &orientation), 0.0);
+}
+
+#[test]
+fn test_ies_lm63_1986_tilt_include() {
+    let text = "Sample luminaire\nTILT=INCLUDE\n1\n2\n0 90\n1.0 0.5\n\
+        1 1000 1.0 2 1 1 1 0 0 0 1.0 1.0 100\n\
+        0 180\n0\n80 20\n";
+    let profile = IesProfile::parse(text).unwrap();
+    let orientation = IesOrientation::new(Vec3::new(0.0, 0.0, -1.0), Vec3::new(1.0, 0.0, 0.0));
+
+    assert_approx_eq!(profile.scale(&Vec3::new(1.0, 0.0, 0.0), &orientation), 0.625);
+}
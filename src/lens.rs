@@ -0,0 +1,376 @@
+use std::fs;
+use std::io::Error;
+use std::io::ErrorKind;
+
+use crate::vec3::Vec3;
+use crate::ray::Ray;
+use crate::utils::radical_inverse;
+
+#[cfg(test)]
+use assert_approx_eq::assert_approx_eq;
+
+const PUPIL_SEGMENTS: usize = 64;
+const PUPIL_SAMPLES: usize = 4096;
+
+#[derive(Debug, Clone, Copy)]
+pub struct LensElement {
+    pub curvature_radius: f64,
+    pub thickness: f64,
+    pub eta: f64,
+    pub aperture_radius: f64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct PupilBounds {
+    min: (f64, f64),
+    max: (f64, f64),
+}
+
+impl PupilBounds {
+    fn area(&self) -> f64 {
+        (self.max.0 - self.min.0).max(0.0) * (self.max.1 - self.min.1).max(0.0)
+    }
+
+    fn lerp(&self, u: (f64, f64)) -> (f64, f64) {
+        (self.min.0 + (self.max.0 - self.min.0) * u.0, self.min.1 + (self.max.1 - self.min.1) * u.1)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LensSystem {
+    pub elements: Vec<LensElement>,
+    pub film_diagonal: f64,
+    pub focus_dist: f64,
+    exit_pupil: Vec<PupilBounds>,
+    axial_area: f64,
+}
+
+impl LensSystem {
+    pub fn new(elements: Vec<LensElement>, film_diagonal: f64, focus_dist: f64) -> Result<LensSystem, Error> {
+        if elements.is_empty() { return Err(Error::new(ErrorKind::InvalidData, "empty lens prescription")); }
+
+        let mut lens = LensSystem { elements, film_diagonal, focus_dist, exit_pupil: vec![], axial_area: 0.0 };
+        let last = lens.elements.len() - 1;
+
+        lens.elements[last].thickness = lens.focus_thick_lens(focus_dist)?;
+        lens.exit_pupil = (0..PUPIL_SEGMENTS).map(|i| {
+            let r0 = (i as f64) / (PUPIL_SEGMENTS as f64) * film_diagonal / 2.0;
+            let r1 = ((i + 1) as f64) / (PUPIL_SEGMENTS as f64) * film_diagonal / 2.0;
+
+            lens.bound_exit_pupil(r0, r1)
+        }).collect();
+        lens.axial_area = lens.effective_pupil_area(&Vec3::new(0.0, 0.0, 0.0));
+
+        if lens.axial_area <= 0.0 { return Err(Error::new(ErrorKind::InvalidData, "no light reaches the film center")); }
+
+        Ok(lens)
+    }
+
+    pub fn framed(&self, vfov: f64, aspect_ratio: f64, focus_dist: f64) -> Result<LensSystem, Error> {
+        let focused = LensSystem::new(self.elements.clone(), self.film_diagonal, focus_dist)?;
+        let (pz, _) = focused.thick_lens_approximation()?;
+        let image_dist = pz[0].abs();
+        let height = 2.0 * image_dist * (vfov.to_radians() / 2.0).tan();
+        let film_diagonal = height * (1.0 + aspect_ratio * aspect_ratio).sqrt();
+
+        if (film_diagonal - self.film_diagonal).abs() < 1e-12 && focus_dist == self.focus_dist { return Ok(self.clone()); }
+
+        LensSystem::new(self.elements.clone(), film_diagonal, focus_dist)
+    }
+
+    pub fn load(path: &str, film_diagonal: f64, focus_dist: f64) -> Result<LensSystem, Error> {
+        LensSystem::new(LensSystem::parse(&fs::read_to_string(path)?)?, film_diagonal, focus_dist)
+    }
+
+    pub fn parse(text: &str) -> Result<Vec<LensElement>, Error> {
+        let mut elements = vec![];
+
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+
+            if line.is_empty() { continue; }
+
+            let values = line.split_whitespace().map(
+                |v| v.parse::<f64>().map_err(|_| Error::new(ErrorKind::InvalidData, format!("invalid lens value: {}", v)))
+            ).collect::<Result<Vec<f64>, Error>>()?;
+
+            if values.len() != 4 { return Err(Error::new(ErrorKind::InvalidData, format!("expected 4 columns: {}", line))); }
+
+            elements.push(LensElement {
+                curvature_radius: values[0] * 0.001,
+                thickness: values[1] * 0.001,
+                eta: values[2],
+                aperture_radius: values[3] * 0.001 / 2.0,
+            });
+        }
+
+        Ok(elements)
+    }
+
+    fn rear_z(&self) -> f64 {
+        self.elements.last().unwrap().thickness
+    }
+
+    fn front_z(&self) -> f64 {
+        self.elements.iter().map(|e| e.thickness).sum()
+    }
+
+    fn rear_radius(&self) -> f64 {
+        self.elements.last().unwrap().aperture_radius
+    }
+
+    fn physical_extent(&self, aspect_ratio: f64) -> (f64, f64) {
+        let x = (self.film_diagonal * self.film_diagonal / (1.0 + 1.0 / (aspect_ratio * aspect_ratio))).sqrt();
+
+        (x, x / aspect_ratio)
+    }
+
+    pub fn trace_from_film(&self, ray: &Ray) -> Option<Ray> {
+        let mut element_z = 0.0;
+        let mut r = Ray::new(flip_z(&ray.orig), flip_z(&ray.dir));
+
+        for i in (0..self.elements.len()).rev() {
+            let element = &self.elements[i];
+
+            element_z -= element.thickness;
+
+            let is_stop = element.curvature_radius == 0.0;
+            let (t, n) = if is_stop {
+                ((element_z - r.orig.z()) / r.dir.z(), Vec3::new(0.0, 0.0, 0.0))
+            } else {
+                intersect_element(element.curvature_radius, element_z + element.curvature_radius, &r)?
+            };
+            let p = r.at(t);
+
+            if p.x() * p.x() + p.y() * p.y() > element.aperture_radius * element.aperture_radius { return None; }
+
+            r.orig = p;
+
+            if !is_stop {
+                let eta_t = if i > 0 && self.elements[i - 1].eta != 0.0 { self.elements[i - 1].eta } else { 1.0 };
+
+                r.dir = refract(&-r.dir.unit_vec(), &n, eta_or_air(element.eta) / eta_t)?;
+            }
+        }
+
+        Some(Ray::new(flip_z(&r.orig), flip_z(&r.dir)))
+    }
+
+    fn trace_from_scene(&self, ray: &Ray) -> Option<Ray> {
+        let mut element_z = -self.front_z();
+        let mut r = Ray::new(flip_z(&ray.orig), flip_z(&ray.dir));
+
+        for i in 0..self.elements.len() {
+            let element = &self.elements[i];
+            let is_stop = element.curvature_radius == 0.0;
+            let (t, n) = if is_stop {
+                ((element_z - r.orig.z()) / r.dir.z(), Vec3::new(0.0, 0.0, 0.0))
+            } else {
+                intersect_element(element.curvature_radius, element_z + element.curvature_radius, &r)?
+            };
+            let p = r.at(t);
+
+            if p.x() * p.x() + p.y() * p.y() > element.aperture_radius * element.aperture_radius { return None; }
+
+            r.orig = p;
+
+            if !is_stop {
+                let eta_i = if i == 0 || self.elements[i - 1].eta == 0.0 { 1.0 } else { self.elements[i - 1].eta };
+
+                r.dir = refract(&-r.dir.unit_vec(), &n, eta_i / eta_or_air(element.eta))?;
+            }
+
+            element_z += element.thickness;
+        }
+
+        Some(Ray::new(flip_z(&r.orig), flip_z(&r.dir)))
+    }
+
+    fn cardinal_points(r_in: &Ray, r_out: &Ray) -> (f64, f64) {
+        let tf = -r_out.orig.x() / r_out.dir.x();
+        let tp = (r_in.orig.x() - r_out.orig.x()) / r_out.dir.x();
+
+        (-r_out.at(tp).z(), -r_out.at(tf).z())
+    }
+
+    fn thick_lens_approximation(&self) -> Result<([f64; 2], [f64; 2]), Error> {
+        let invalid = || Error::new(ErrorKind::InvalidData, "lens does not focus paraxial rays");
+        let x = 0.001 * self.film_diagonal;
+        let r_scene = Ray::new(Vec3::new(x, 0.0, self.front_z() + 1.0), Vec3::new(0.0, 0.0, -1.0));
+        let r_film = self.trace_from_scene(&r_scene).ok_or_else(invalid)?;
+        let (pz0, fz0) = LensSystem::cardinal_points(&r_scene, &r_film);
+        let r_film = Ray::new(Vec3::new(x, 0.0, self.rear_z() - 1.0), Vec3::new(0.0, 0.0, 1.0));
+        let r_scene = self.trace_from_film(&r_film).ok_or_else(invalid)?;
+        let (pz1, fz1) = LensSystem::cardinal_points(&r_film, &r_scene);
+
+        Ok(([pz0, pz1], [fz0, fz1]))
+    }
+
+    fn focus_thick_lens(&self, focus_dist: f64) -> Result<f64, Error> {
+        let (pz, fz) = self.thick_lens_approximation()?;
+        let f = fz[0] - pz[0];
+        let z = -focus_dist;
+        let c = (pz[1] - z - pz[0]) * (pz[1] - z - 4.0 * f - pz[0]);
+
+        if c <= 0.0 { return Err(Error::new(ErrorKind::InvalidData, "focus distance is too short for this lens")); }
+
+        let delta = 0.5 * (pz[1] - z + pz[0] - c.sqrt());
+
+        Ok(self.rear_z() + delta)
+    }
+
+    fn effective_pupil_area(&self, film: &Vec3) -> f64 {
+        let rear = 1.5 * self.rear_radius();
+        let mut hits = 0;
+
+        for i in 0..PUPIL_SAMPLES {
+            let (x, y) = (rear * (2.0 * radical_inverse(2, i as u64) - 1.0), rear * (2.0 * radical_inverse(3, i as u64) - 1.0));
+            let ray = Ray::new(*film, Vec3::new(x, y, self.rear_z()) - *film);
+
+            if self.trace_from_film(&ray).is_some() { hits += 1; }
+        }
+
+        4.0 * rear * rear * (hits as f64) / (PUPIL_SAMPLES as f64)
+    }
+
+    fn bound_exit_pupil(&self, r0: f64, r1: f64) -> PupilBounds {
+        let rear = 1.5 * self.rear_radius();
+        let rear_bounds = PupilBounds { min: (-rear, -rear), max: (rear, rear) };
+        let mut bounds: Option<PupilBounds> = None;
+
+        for i in 0..PUPIL_SAMPLES {
+            let film = Vec3::new(r0 + (r1 - r0) * ((i as f64) + 0.5) / (PUPIL_SAMPLES as f64), 0.0, 0.0);
+            let (x, y) = rear_bounds.lerp((radical_inverse(2, i as u64), radical_inverse(3, i as u64)));
+            let rear_pt = Vec3::new(x, y, self.rear_z());
+
+            if self.trace_from_film(&Ray::new(film, rear_pt - film)).is_none() { continue; }
+
+            bounds = Some(match bounds {
+                Some(b) => PupilBounds { min: (b.min.0.min(x), b.min.1.min(y)), max: (b.max.0.max(x), b.max.1.max(y)) },
+                None => PupilBounds { min: (x, y), max: (x, y) },
+            });
+        }
+
+        let Some(bounds) = bounds else { return rear_bounds; };
+        let pad = 2.0 * 2.0_f64.sqrt() * 2.0 * rear / (PUPIL_SAMPLES as f64).sqrt();
+
+        PupilBounds { min: (bounds.min.0 - pad, bounds.min.1 - pad), max: (bounds.max.0 + pad, bounds.max.1 + pad) }
+    }
+
+    pub fn sample_ray(&self, s: f64, t: f64, aspect_ratio: f64, u: (f64, f64)) -> Option<(Ray, f64)> {
+        let (width, height) = self.physical_extent(aspect_ratio);
+        let film = Vec3::new(-(s - 0.5) * width, (t - 0.5) * height, 0.0);
+        let r_film = (film.x() * film.x() + film.y() * film.y()).sqrt();
+        let segment = ((r_film / (self.film_diagonal / 2.0) * PUPIL_SEGMENTS as f64) as usize).min(PUPIL_SEGMENTS - 1);
+        let bounds = self.exit_pupil[segment];
+        let (px, py) = bounds.lerp(u);
+        let (sin_theta, cos_theta) = if r_film > 0.0 { (film.y() / r_film, film.x() / r_film) } else { (0.0, 1.0) };
+        let rear_pt = Vec3::new(cos_theta * px - sin_theta * py, sin_theta * px + cos_theta * py, self.rear_z());
+        let ray = Ray::new(film, rear_pt - film);
+        let out = self.trace_from_film(&ray)?;
+        let cos4 = ray.dir.unit_vec().z().powi(4);
+
+        // Film irradiance is E = L * cos^4 * A / z^2 for a rear pupil of area A at distance z. Dividing by the
+        // on-axis E keeps the physical falloff but maps a uniform radiance L to L at the center of the image.
+        Some((out, cos4 * bounds.area() / self.axial_area))
+    }
+}
+
+fn flip_z(v: &Vec3) -> Vec3 {
+    Vec3::new(v.x(), v.y(), -v.z())
+}
+
+fn eta_or_air(eta: f64) -> f64 {
+    if eta == 0.0 { 1.0 } else { eta }
+}
+
+fn intersect_element(radius: f64, z_center: f64, ray: &Ray) -> Option<(f64, Vec3)> {
+    let o = ray.orig - Vec3::new(0.0, 0.0, z_center);
+    let a = ray.dir.len_sqr();
+    let b = 2.0 * ray.dir.dot(&o);
+    let c = o.len_sqr() - radius * radius;
+    let discriminant = b * b - 4.0 * a * c;
+
+    if discriminant < 0.0 { return None; }
+
+    let sqrtd = discriminant.sqrt();
+    let q = if b < 0.0 { -0.5 * (b - sqrtd) } else { -0.5 * (b + sqrtd) };
+    let (t0, t1) = (q / a, c / q);
+    let (t0, t1) = (t0.min(t1), t0.max(t1));
+    let use_closer = (ray.dir.z() > 0.0) ^ (radius < 0.0);
+    let t = if use_closer { t0 } else { t1 };
+
+    if t < 0.0 { return None; }
+
+    let n = (o + ray.dir * t).unit_vec();
+    let n = if n.dot(&-ray.dir) < 0.0 { -n } else { n };
+
+    Some((t, n))
+}
+
+fn refract(wi: &Vec3, n: &Vec3, eta: f64) -> Option<Vec3> {
+    let cos_theta_i = n.dot(wi);
+    let sin2_theta_t = eta * eta * (1.0 - cos_theta_i * cos_theta_i).max(0.0);
+
+    if sin2_theta_t >= 1.0 { return None; }
+
+    let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+
+    Some(-*wi * eta + *n * (eta * cos_theta_i - cos_theta_t))
+}
+
+#[test]
+fn test_singlet_focus() {
+    let prescription = "# radius thickness ior aperture\n\
+        50.0 5.0 1.5 20.0\n\
+        -50.0 2.0 1.0 20.0\n\
+        0.0 45.0 0.0 1.0\n";
+    let lens = LensSystem::new(LensSystem::parse(prescription).unwrap(), 35.0 * 0.001, 2.0).unwrap();
+    let mut hits = 0;
+    let mut weight = 0.0;
+
+    for i in 0..1024 {
+        let u = (radical_inverse(2, i), radical_inverse(3, i));
+        let Some((ray, w)) = lens.sample_ray(0.5, 0.5, 1.5, u) else { continue; };
+        let t = -ray.orig.x() / ray.dir.x();
+
+        hits += 1;
+        weight += w;
+
+        if ray.orig.x().abs() > 1e-5 { assert_approx_eq!(ray.at(t).z(), 2.0, 0.05); }
+    }
+
+    assert!(hits > 256);
+    assert_approx_eq!(weight / 1024.0, 1.0, 0.02);
+}
+
+#[test]
+fn test_off_axis_lens() {
+    let prescription = "50.0 5.0 1.5 20.0\n-50.0 2.0 1.0 20.0\n0.0 45.0 0.0 1.0\n";
+    let lens = LensSystem::new(LensSystem::parse(prescription).unwrap(), 35.0 * 0.001, 1.0).unwrap().framed(20.0, 1.5, 2.0).unwrap();
+    let film = Vec3::new(0.0, -lens.physical_extent(1.5).1 / 2.0, 0.0);
+    let rear = 1.5 * lens.rear_radius();
+    let (mut weight, mut expected) = (0.0, 0.0);
+    let mut focus = vec![];
+
+    for i in 0..4096 {
+        let u = (radical_inverse(2, i), radical_inverse(3, i));
+        let rear_pt = Vec3::new(rear * (2.0 * u.0 - 1.0), rear * (2.0 * u.1 - 1.0), lens.rear_z());
+        let ray = Ray::new(film, rear_pt - film);
+
+        if lens.trace_from_film(&ray).is_some() { expected += ray.dir.unit_vec().z().powi(4) * 4.0 * rear * rear / lens.axial_area; }
+
+        let Some((ray, w)) = lens.sample_ray(0.5, 0.0, 1.5, u) else { continue; };
+
+        weight += w;
+        focus.push(ray.at((2.0 - ray.orig.z()) / ray.dir.z()).y());
+    }
+
+    let (lo, hi) = focus.iter().fold((f64::MAX, f64::MIN), |(lo, hi), y| (lo.min(*y), hi.max(*y)));
+    let (pz, _) = lens.thick_lens_approximation().unwrap();
+
+    assert!(weight / 4096.0 < 0.95);
+    assert_approx_eq!(weight / 4096.0, expected / 4096.0, 0.02);
+    assert_approx_eq!((lo + hi) / 2.0, (2.0 + pz[1]) * 10.0_f64.to_radians().tan(), 0.005);
+    assert!(hi - lo < 0.01);
+}
@@ -3,4 +3,20 @@ pub mod ray;
 pub mod sphere;
 pub mod materials;
 pub mod camera;
-pub mod utils;
\ No newline at end of file
+pub mod utils;
+pub mod spectrum;
+pub mod microfacet;
+pub mod scene;
+pub mod texture;
+pub mod thin_film;
+pub mod medium;
+pub mod bump;
+pub mod light;
+pub mod distribution;
+pub mod sky;
+pub mod ies;
+pub mod light_tree;
+pub mod lens;
+pub mod aperture;
+pub mod sampler;
+pub mod filter;
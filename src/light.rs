@@ -0,0 +1,268 @@
+use std::f64::consts::PI;
+use serde::{ Serialize, Deserialize };
+
+use crate::vec3::Vec3;
+use crate::utils::onb;
+use crate::ies::IesProfile;
+use crate::ies::IesOrientation;
+use crate::sampler::PixelSampler;
+
+#[cfg(test)]
+use assert_approx_eq::assert_approx_eq;
+#[cfg(test)]
+use crate::sampler::Samplers;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum Lights {
+    Point(PointLight),
+    Spot(SpotLight),
+    Directional(DirectionalLight),
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct LightLink {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub shadow_exclude: Vec<String>,
+}
+
+impl LightLink {
+    pub fn illuminates(&self, name: &str) -> bool {
+        if !self.include.is_empty() && !self.include.iter().any(|n| n == name) { return false; }
+
+        !self.exclude.iter().any(|n| n == name)
+    }
+
+    pub fn casts_shadow(&self, name: &str) -> bool {
+        !self.shadow_exclude.iter().any(|n| n == name)
+    }
+}
+
+pub struct LightSample {
+    pub wi: Vec3,
+    pub dist: f64,
+    pub radiance: Vec3,
+    pub pdf: f64,
+}
+
+pub trait Light {
+    fn sample_li(&self, pt: &Vec3, sampler: &mut PixelSampler) -> Option<LightSample>;
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PointLight {
+    pub position: Vec3,
+    pub intensity: Vec3,
+    #[serde(default)]
+    pub ies: Option<IesProfile>,
+    #[serde(default)]
+    pub ies_orientation: IesOrientation,
+    #[serde(default)]
+    pub link: LightLink,
+}
+
+impl PointLight {
+    pub fn new(position: Vec3, intensity: Vec3) -> PointLight {
+        PointLight { position, intensity, ies: None, ies_orientation: IesOrientation::default(), link: LightLink::default() }
+    }
+
+    pub fn with_ies(self, ies: IesProfile) -> PointLight {
+        PointLight { ies: Some(ies), ..self }
+    }
+
+    pub fn with_ies_orientation(self, nadir: Vec3, zero_azimuth: Vec3) -> PointLight {
+        PointLight { ies_orientation: IesOrientation::new(nadir, zero_azimuth), ..self }
+    }
+}
+
+impl Light for PointLight {
+    fn sample_li(&self, pt: &Vec3, _sampler: &mut PixelSampler) -> Option<LightSample> {
+        let to_light = self.position - *pt;
+        let dist_sqr = to_light.len_sqr();
+
+        if dist_sqr == 0.0 { return None; }
+
+        let dist = dist_sqr.sqrt();
+        let wi = to_light / dist;
+        let profile = match &self.ies {
+            Some(ies) => ies.scale(&-wi, &self.ies_orientation),
+            None => 1.0,
+        };
+
+        if profile <= 0.0 { return None; }
+
+        Some(LightSample { wi, dist, radiance: self.intensity * (profile / dist_sqr), pdf: 1.0 })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpotLight {
+    pub position: Vec3,
+    pub direction: Vec3,
+    pub intensity: Vec3,
+    pub inner_angle: f64,
+    pub outer_angle: f64,
+    #[serde(default)]
+    pub ies: Option<IesProfile>,
+    #[serde(default)]
+    pub ies_orientation: IesOrientation,
+    #[serde(default)]
+    pub link: LightLink,
+}
+
+impl SpotLight {
+    pub fn new(position: Vec3, direction: Vec3, intensity: Vec3, inner_angle: f64, outer_angle: f64) -> SpotLight {
+        SpotLight { position, direction, intensity, inner_angle, outer_angle, ies: None, ies_orientation: IesOrientation::default(), link: LightLink::default() }
+    }
+
+    pub fn with_ies(self, ies: IesProfile) -> SpotLight {
+        SpotLight { ies: Some(ies), ..self }
+    }
+
+    pub fn with_ies_orientation(self, nadir: Vec3, zero_azimuth: Vec3) -> SpotLight {
+        SpotLight { ies_orientation: IesOrientation::new(nadir, zero_azimuth), ..self }
+    }
+
+    fn falloff(&self, cos_theta: f64) -> f64 {
+        let cos_inner = self.inner_angle.to_radians().cos();
+        let cos_outer = self.outer_angle.to_radians().cos();
+
+        if cos_inner <= cos_outer { return if cos_theta >= cos_outer { 1.0 } else { 0.0 }; }
+
+        let t = ((cos_theta - cos_outer) / (cos_inner - cos_outer)).clamp(0.0, 1.0);
+
+        t * t * (3.0 - 2.0 * t)
+    }
+}
+
+impl Light for SpotLight {
+    fn sample_li(&self, pt: &Vec3, _sampler: &mut PixelSampler) -> Option<LightSample> {
+        let to_light = self.position - *pt;
+        let dist_sqr = to_light.len_sqr();
+
+        if dist_sqr == 0.0 { return None; }
+
+        let dist = dist_sqr.sqrt();
+        let wi = to_light / dist;
+        let falloff = match &self.ies {
+            Some(ies) => self.falloff(-wi.dot(&self.direction.unit_vec())) * ies.scale(&-wi, &self.ies_orientation),
+            None => self.falloff(-wi.dot(&self.direction.unit_vec())),
+        };
+
+        if falloff <= 0.0 { return None; }
+
+        Some(LightSample { wi, dist, radiance: self.intensity * (falloff / dist_sqr), pdf: 1.0 })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DirectionalLight {
+    pub direction: Vec3,
+    pub irradiance: Vec3,
+    #[serde(default)]
+    pub angular_diameter: f64,
+    #[serde(default)]
+    pub link: LightLink,
+}
+
+impl DirectionalLight {
+    pub fn new(direction: Vec3, irradiance: Vec3, angular_diameter: f64) -> DirectionalLight {
+        DirectionalLight { direction, irradiance, angular_diameter, link: LightLink::default() }
+    }
+}
+
+impl Light for DirectionalLight {
+    fn sample_li(&self, _pt: &Vec3, sampler: &mut PixelSampler) -> Option<LightSample> {
+        let axis = -self.direction.unit_vec();
+
+        if self.angular_diameter <= 0.0 {
+            return Some(LightSample { wi: axis, dist: f64::INFINITY, radiance: self.irradiance, pdf: 1.0 });
+        }
+
+        let cos_max = (self.angular_diameter.to_radians() / 2.0).cos();
+        let cos_theta = 1.0 - sampler.get_1d() * (1.0 - cos_max);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * sampler.get_1d();
+        let (t, b) = onb(&axis);
+        let wi = t * (sin_theta * phi.cos()) + b * (sin_theta * phi.sin()) + axis * cos_theta;
+
+        Some(LightSample { wi, dist: f64::INFINITY, radiance: self.irradiance, pdf: 1.0 })
+    }
+}
+
+impl Lights {
+    pub fn link(&self) -> &LightLink {
+        match self {
+            Lights::Point(p) => &p.link,
+            Lights::Spot(s) => &s.link,
+            Lights::Directional(d) => &d.link,
+        }
+    }
+
+    pub fn with_link(self, link: LightLink) -> Lights {
+        match self {
+            Lights::Point(p) => Lights::Point(PointLight { link, ..p }),
+            Lights::Spot(s) => Lights::Spot(SpotLight { link, ..s }),
+            Lights::Directional(d) => Lights::Directional(DirectionalLight { link, ..d }),
+        }
+    }
+}
+
+impl Light for Lights {
+    fn sample_li(&self, pt: &Vec3, sampler: &mut PixelSampler) -> Option<LightSample> {
+        match self {
+            Lights::Point(p) => { p.sample_li(pt, sampler) }
+            Lights::Spot(s) => { s.sample_li(pt, sampler) }
+            Lights::Directional(d) => { d.sample_li(pt, sampler) }
+        }
+    }
+}
+
+#[test]
+fn test_point_light() {
+    let light = PointLight::new(Vec3::new(0.0, 2.0, 0.0), Vec3::new(4.0, 4.0, 4.0));
+    let mut sampler = PixelSampler::new(Samplers::default(), 0, (0, 0), 0);
+    let sample = light.sample_li(&Vec3::new(0.0, 0.0, 0.0), &mut sampler).unwrap();
+
+    assert_approx_eq!(sample.dist, 2.0);
+    assert_approx_eq!(sample.wi.y(), 1.0);
+    assert_approx_eq!(sample.radiance.x(), 1.0);
+}
+
+#[test]
+fn test_spot_light_cone() {
+    let light = SpotLight::new(
+        Vec3::new(0.0, 1.0, 0.0),
+        Vec3::new(0.0, -1.0, 0.0),
+        Vec3::new(1.0, 1.0, 1.0),
+        20.0,
+        30.0,
+    );
+    let mut sampler = PixelSampler::new(Samplers::default(), 0, (0, 0), 0);
+
+    assert_approx_eq!(light.sample_li(&Vec3::new(0.0, 0.0, 0.0), &mut sampler).unwrap().radiance.x(), 1.0);
+    assert!(light.sample_li(&Vec3::new(1.0, 0.0, 0.0), &mut sampler).is_none());
+}
+
+#[test]
+fn test_rotated_ies_profile() {
+    let text = "IESNA:LM-63-1995\nTILT=NONE\n\
+        1 1000 1.0 3 3 1 1 0 0 0\n\
+        1.0 1.0 100\n\
+        0 45 90\n\
+        0 90 180\n\
+        100 50 0\n\
+        100 40 0\n\
+        100 30 0\n";
+    let light = PointLight::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 1.0))
+        .with_ies(IesProfile::parse(text).unwrap())
+        .with_ies_orientation(Vec3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0));
+    let mut sampler = PixelSampler::new(Samplers::default(), 0, (0, 0), 0);
+
+    assert_approx_eq!(light.sample_li(&Vec3::new(1.0, 0.0, 0.0), &mut sampler).unwrap().radiance.x(), 1.0);
+    assert_approx_eq!(light.sample_li(&Vec3::new(1.0, 0.0, 1.0), &mut sampler).unwrap().radiance.x(), 0.25);
+    assert_approx_eq!(light.sample_li(&Vec3::new(1.0, 0.0, -1.0), &mut sampler).unwrap().radiance.x(), 0.15);
+    assert!(light.sample_li(&Vec3::new(0.0, -1.0, 0.0), &mut sampler).is_none());
+}
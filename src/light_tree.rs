@@ -0,0 +1,441 @@
+use std::f64::consts::PI;
+use std::collections::HashMap;
+
+use crate::vec3::Vec3;
+use crate::sphere::Sphere;
+use crate::materials::Materials;
+use crate::materials::luminance;
+use crate::texture::Texture;
+use crate::light::Lights;
+
+#[cfg(test)]
+use assert_approx_eq::assert_approx_eq;
+#[cfg(test)]
+use crate::materials::DiffuseLight;
+#[cfg(test)]
+use crate::light::PointLight;
+
+const BUCKETS: usize = 12;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TreeLight {
+    Object(usize),
+    Light(usize),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct LightBounds {
+    pub min: Vec3,
+    pub max: Vec3,
+    pub w: Vec3,
+    pub phi: f64,
+    pub cos_theta_o: f64,
+    pub cos_theta_e: f64,
+    pub two_sided: bool,
+}
+
+impl LightBounds {
+    pub fn centroid(&self) -> Vec3 {
+        (self.min + self.max) / 2.0
+    }
+
+    pub fn union(&self, other: &LightBounds) -> LightBounds {
+        if self.phi <= 0.0 { return *other; }
+        if other.phi <= 0.0 { return *self; }
+
+        let (w, cos_theta_o) = cone_union((self.w, self.cos_theta_o), (other.w, other.cos_theta_o));
+
+        LightBounds {
+            min: min_vec(&self.min, &other.min),
+            max: max_vec(&self.max, &other.max),
+            w,
+            phi: self.phi + other.phi,
+            cos_theta_o,
+            cos_theta_e: self.cos_theta_e.min(other.cos_theta_e),
+            two_sided: self.two_sided || other.two_sided,
+        }
+    }
+
+    pub fn importance(&self, p: &Vec3, n: &Vec3) -> f64 {
+        let pc = self.centroid();
+        let half_diag = (self.max - self.min).len() / 2.0;
+        let d2 = (*p - pc).len_sqr().max(half_diag);
+
+        if d2 <= 0.0 { return self.phi; }
+
+        let wi = (*p - pc).unit_vec();
+        let mut cos_theta_w = self.w.dot(&wi);
+
+        if self.two_sided { cos_theta_w = cos_theta_w.abs(); }
+
+        let sin_theta_w = safe_sqrt(1.0 - cos_theta_w * cos_theta_w);
+        let cos_theta_b = if (*p - pc).len_sqr() < half_diag * half_diag {
+            -1.0
+        } else {
+            safe_sqrt(1.0 - half_diag * half_diag / (*p - pc).len_sqr())
+        };
+        let sin_theta_b = safe_sqrt(1.0 - cos_theta_b * cos_theta_b);
+        let sin_theta_o = safe_sqrt(1.0 - self.cos_theta_o * self.cos_theta_o);
+        let cos_theta_x = cos_sub_clamped(sin_theta_w, cos_theta_w, sin_theta_o, self.cos_theta_o);
+        let sin_theta_x = sin_sub_clamped(sin_theta_w, cos_theta_w, sin_theta_o, self.cos_theta_o);
+        let cos_theta_p = cos_sub_clamped(sin_theta_x, cos_theta_x, sin_theta_b, cos_theta_b);
+
+        if cos_theta_p <= self.cos_theta_e { return 0.0; }
+
+        let mut importance = self.phi * cos_theta_p / d2;
+
+        if !n.near_zero() {
+            let cos_theta_i = wi.dot(n).abs();
+            let sin_theta_i = safe_sqrt(1.0 - cos_theta_i * cos_theta_i);
+
+            importance *= cos_sub_clamped(sin_theta_i, cos_theta_i, sin_theta_b, cos_theta_b);
+        }
+
+        importance.max(0.0)
+    }
+}
+
+#[derive(Debug, Clone)]
+enum LightNode {
+    Leaf { bounds: LightBounds, light: TreeLight },
+    Interior { bounds: LightBounds, second: usize },
+}
+
+impl LightNode {
+    fn bounds(&self) -> &LightBounds {
+        match self {
+            LightNode::Leaf { bounds, .. } => bounds,
+            LightNode::Interior { bounds, .. } => bounds,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LightTree {
+    nodes: Vec<LightNode>,
+    trails: HashMap<TreeLight, u64>,
+}
+
+impl LightTree {
+    pub fn new(objects: &[Sphere], lights: &[Lights]) -> LightTree {
+        let mut leaves: Vec<(TreeLight, LightBounds)> = vec![];
+
+        for (i, sphere) in objects.iter().enumerate() {
+            if let Some(bounds) = sphere_bounds(sphere) { leaves.push((TreeLight::Object(i), bounds)); }
+        }
+
+        for (i, light) in lights.iter().enumerate() {
+            if let Some(bounds) = light_bounds(light) { leaves.push((TreeLight::Light(i), bounds)); }
+        }
+
+        let mut tree = LightTree::default();
+
+        if !leaves.is_empty() { tree.build(&mut leaves, 0, 0); }
+
+        tree
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    fn build(&mut self, leaves: &mut [(TreeLight, LightBounds)], trail: u64, depth: u32) -> usize {
+        let index = self.nodes.len();
+
+        if leaves.len() == 1 {
+            self.nodes.push(LightNode::Leaf { bounds: leaves[0].1, light: leaves[0].0 });
+            self.trails.insert(leaves[0].0, trail);
+
+            return index;
+        }
+
+        let capacity = 1_u64 << (62 - depth);
+        let mid = split(leaves);
+        let mid = if mid.max(leaves.len() - mid) as u64 > capacity { leaves.len() / 2 } else { mid };
+        let bounds = leaves.iter().skip(1).fold(leaves[0].1, |b, l| b.union(&l.1));
+
+        self.nodes.push(LightNode::Interior { bounds, second: 0 });
+
+        let (left, right) = leaves.split_at_mut(mid);
+
+        self.build(left, trail, depth + 1);
+
+        let second = self.build(right, trail | (1 << depth), depth + 1);
+
+        if let LightNode::Interior { second: s, .. } = &mut self.nodes[index] { *s = second; }
+
+        index
+    }
+
+    pub fn sample(&self, p: &Vec3, n: &Vec3, mut u: f64) -> Option<(TreeLight, f64)> {
+        if self.nodes.is_empty() { return None; }
+
+        let mut index = 0;
+        let mut pmf = 1.0;
+
+        loop {
+            match &self.nodes[index] {
+                LightNode::Leaf { bounds, light } => {
+                    if bounds.importance(p, n) <= 0.0 { return None; }
+
+                    return Some((*light, pmf));
+                }
+                LightNode::Interior { second, .. } => {
+                    let ci = [self.nodes[index + 1].bounds().importance(p, n), self.nodes[*second].bounds().importance(p, n)];
+
+                    if ci[0] <= 0.0 && ci[1] <= 0.0 { return None; }
+
+                    let p0 = ci[0] / (ci[0] + ci[1]);
+
+                    if u < p0 {
+                        u = (u / p0).min(1.0 - f64::EPSILON);
+                        pmf *= p0;
+                        index += 1;
+                    } else {
+                        u = ((u - p0) / (1.0 - p0)).min(1.0 - f64::EPSILON);
+                        pmf *= 1.0 - p0;
+                        index = *second;
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn pmf(&self, p: &Vec3, n: &Vec3, light: TreeLight) -> f64 {
+        let Some(mut trail) = self.trails.get(&light).copied() else { return 0.0; };
+        let mut index = 0;
+        let mut pmf = 1.0;
+
+        while let LightNode::Interior { second, .. } = &self.nodes[index] {
+            let ci = [self.nodes[index + 1].bounds().importance(p, n), self.nodes[*second].bounds().importance(p, n)];
+            let child = (trail & 1) as usize;
+
+            if ci[child] <= 0.0 { return 0.0; }
+
+            pmf *= ci[child] / (ci[0] + ci[1]);
+            index = if child == 0 { index + 1 } else { *second };
+            trail >>= 1;
+        }
+
+        pmf
+    }
+}
+
+fn split(leaves: &mut [(TreeLight, LightBounds)]) -> usize {
+    let total = leaves.iter().skip(1).fold(leaves[0].1, |b, l| b.union(&l.1));
+    let mut centroid_min = leaves[0].1.centroid();
+    let mut centroid_max = centroid_min;
+
+    for (_, b) in leaves.iter() {
+        centroid_min = min_vec(&centroid_min, &b.centroid());
+        centroid_max = max_vec(&centroid_max, &b.centroid());
+    }
+
+    let extent = total.max - total.min;
+    let max_extent = extent.x().max(extent.y()).max(extent.z());
+    let mut best: Option<(f64, usize, f64)> = None;
+
+    for axis in 0..3 {
+        let lo = component(&centroid_min, axis);
+        let hi = component(&centroid_max, axis);
+
+        if hi <= lo { continue; }
+
+        let bucket = |b: &LightBounds| (((component(&b.centroid(), axis) - lo) / (hi - lo) * BUCKETS as f64) as usize).min(BUCKETS - 1);
+        let mut buckets: [Option<LightBounds>; BUCKETS] = [None; BUCKETS];
+
+        for (_, b) in leaves.iter() {
+            let i = bucket(b);
+
+            buckets[i] = Some(buckets[i].map_or(*b, |x| x.union(b)));
+        }
+
+        let kr = max_extent / component(&extent, axis).max(1e-9);
+
+        for cut in 1..BUCKETS {
+            let below = buckets[..cut].iter().flatten().fold(None, |acc: Option<LightBounds>, b| Some(acc.map_or(*b, |a| a.union(b))));
+            let above = buckets[cut..].iter().flatten().fold(None, |acc: Option<LightBounds>, b| Some(acc.map_or(*b, |a| a.union(b))));
+            let (Some(below), Some(above)) = (below, above) else { continue; };
+            let cost = kr * (cost(&below) + cost(&above)) / cost(&total).max(1e-12);
+
+            if best.is_none_or(|(c, _, _)| cost < c) {
+                best = Some((cost, axis, lo + (hi - lo) * (cut as f64) / BUCKETS as f64));
+            }
+        }
+    }
+
+    let mid = match best {
+        Some((_, axis, pos)) => {
+            let mut mid = 0;
+
+            for i in 0..leaves.len() {
+                if component(&leaves[i].1.centroid(), axis) < pos {
+                    leaves.swap(i, mid);
+                    mid += 1;
+                }
+            }
+
+            mid
+        }
+        None => leaves.len() / 2,
+    };
+
+    if mid == 0 || mid == leaves.len() { leaves.len() / 2 } else { mid }
+}
+
+fn cost(b: &LightBounds) -> f64 {
+    let theta_o = b.cos_theta_o.clamp(-1.0, 1.0).acos();
+    let theta_e = b.cos_theta_e.clamp(-1.0, 1.0).acos();
+    let theta_w = (theta_o + theta_e).min(PI);
+    let sin_theta_o = theta_o.sin();
+    let m_omega = 2.0 * PI * (1.0 - b.cos_theta_o)
+        + PI / 2.0 * (2.0 * theta_w * sin_theta_o - (theta_o - 2.0 * theta_w).cos() - 2.0 * theta_o * sin_theta_o + b.cos_theta_o);
+    let d = b.max - b.min;
+    let area = 2.0 * (d.x() * d.y() + d.y() * d.z() + d.z() * d.x());
+
+    b.phi * m_omega * area.max(1e-9)
+}
+
+fn sphere_bounds(sphere: &Sphere) -> Option<LightBounds> {
+    let Materials::DiffuseLight(light) = &sphere.mat else { return None; };
+    let emit = luminance(&light.emit.value(0.5, 0.5, &sphere.center));
+    let r = Vec3::new(sphere.radius, sphere.radius, sphere.radius);
+
+    if emit <= 0.0 { return None; }
+
+    Some(LightBounds {
+        min: sphere.center - r,
+        max: sphere.center + r,
+        w: Vec3::new(0.0, 1.0, 0.0),
+        phi: emit * 4.0 * PI * sphere.radius * sphere.radius * PI,
+        cos_theta_o: -1.0,
+        cos_theta_e: 0.0,
+        two_sided: false,
+    })
+}
+
+fn light_bounds(light: &Lights) -> Option<LightBounds> {
+    match light {
+        Lights::Point(p) => Some(LightBounds {
+            min: p.position,
+            max: p.position,
+            w: Vec3::new(0.0, 1.0, 0.0),
+            phi: 4.0 * PI * luminance(&p.intensity),
+            cos_theta_o: -1.0,
+            cos_theta_e: 0.0,
+            two_sided: false,
+        }),
+        Lights::Spot(s) => {
+            let cos_inner = s.inner_angle.min(s.outer_angle).to_radians().cos();
+            let theta_e = (s.outer_angle - s.inner_angle).max(0.0).to_radians();
+
+            Some(LightBounds {
+                min: s.position,
+                max: s.position,
+                w: s.direction.unit_vec(),
+                phi: 4.0 * PI * luminance(&s.intensity),
+                cos_theta_o: cos_inner,
+                cos_theta_e: theta_e.cos(),
+                two_sided: false,
+            })
+        }
+        Lights::Directional(_) => None,
+    }
+}
+
+fn cone_union(a: (Vec3, f64), b: (Vec3, f64)) -> (Vec3, f64) {
+    let theta_a = a.1.clamp(-1.0, 1.0).acos();
+    let theta_b = b.1.clamp(-1.0, 1.0).acos();
+    let theta_d = a.0.dot(&b.0).clamp(-1.0, 1.0).acos();
+
+    if (theta_d + theta_b).min(PI) <= theta_a { return a; }
+    if (theta_d + theta_a).min(PI) <= theta_b { return b; }
+
+    let theta_o = (theta_a + theta_d + theta_b) / 2.0;
+
+    if theta_o >= PI { return (a.0, -1.0); }
+
+    let theta_r = theta_o - theta_a;
+    let axis = a.0.cross(&b.0);
+
+    if axis.len_sqr() == 0.0 { return (a.0, -1.0); }
+
+    let k = axis.unit_vec();
+    let w = a.0 * theta_r.cos() + k.cross(&a.0) * theta_r.sin() + k * (k.dot(&a.0) * (1.0 - theta_r.cos()));
+
+    (w, theta_o.cos())
+}
+
+fn cos_sub_clamped(sin_a: f64, cos_a: f64, sin_b: f64, cos_b: f64) -> f64 {
+    if cos_a > cos_b { return 1.0; }
+
+    cos_a * cos_b + sin_a * sin_b
+}
+
+fn sin_sub_clamped(sin_a: f64, cos_a: f64, sin_b: f64, cos_b: f64) -> f64 {
+    if cos_a > cos_b { return 0.0; }
+
+    sin_a * cos_b - cos_a * sin_b
+}
+
+fn safe_sqrt(x: f64) -> f64 {
+    x.max(0.0).sqrt()
+}
+
+fn component(v: &Vec3, axis: usize) -> f64 {
+    match axis {
+        0 => v.x(),
+        1 => v.y(),
+        _ => v.z(),
+    }
+}
+
+fn min_vec(a: &Vec3, b: &Vec3) -> Vec3 {
+    Vec3::new(a.x().min(b.x()), a.y().min(b.y()), a.z().min(b.z()))
+}
+
+fn max_vec(a: &Vec3, b: &Vec3) -> Vec3 {
+    Vec3::new(a.x().max(b.x()), a.y().max(b.y()), a.z().max(b.z()))
+}
+
+#[test]
+fn test_light_tree_pmf() {
+    let objects: Vec<Sphere> = (0..50).map(
+        |i| Sphere::new(
+            Vec3::new((i % 10) as f64 * 3.0, 5.0, (i / 10) as f64 * 3.0),
+            0.5,
+            Materials::DiffuseLight(DiffuseLight::new(Vec3::new(1.0 + i as f64, 1.0, 1.0))),
+        )
+    ).collect();
+    let tree = LightTree::new(&objects, &[]);
+    let p = Vec3::new(1.0, 0.0, 1.0);
+    let n = Vec3::new(0.0, 1.0, 0.0);
+    let total: f64 = (0..50).map(|i| tree.pmf(&p, &n, TreeLight::Object(i))).sum();
+
+    assert_approx_eq!(total, 1.0);
+
+    for i in 0..100 {
+        let (light, pmf) = tree.sample(&p, &n, ((i as f64) + 0.5) / 100.0).unwrap();
+
+        assert_approx_eq!(tree.pmf(&p, &n, light), pmf);
+    }
+
+    let near = tree.pmf(&p, &n, TreeLight::Object(0));
+    let far = tree.pmf(&p, &n, TreeLight::Object(49));
+
+    assert!(near > far);
+}
+
+#[test]
+fn test_deep_light_tree() {
+    let lights: Vec<Lights> = (0..70).map(
+        |i| Lights::Point(PointLight::new(Vec3::new(16.0_f64.powi(i), 1.0, 0.0), Vec3::new(1.0, 1.0, 1.0)))
+    ).collect();
+    let tree = LightTree::new(&[], &lights);
+    let p = Vec3::new(0.0, 0.0, 0.0);
+    let n = Vec3::new(0.0, 1.0, 0.0);
+    let pmfs: Vec<f64> = (0..70).map(|i| tree.pmf(&p, &n, TreeLight::Light(i))).collect();
+
+    assert!(pmfs.iter().all(|pmf| *pmf > 0.0));
+    assert_approx_eq!(pmfs.iter().sum::<f64>(), 1.0);
+}
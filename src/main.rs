@@ -1,4 +1,6 @@
 use rand::Rng;
+use rand::SeedableRng;
+use rand::rngs::SmallRng;
 
 use rust_raytracer::vec3::Vec3;
 use rust_raytracer::sphere::Sphere;
@@ -7,6 +9,7 @@ use rust_raytracer::materials::Lambertian;
 use rust_raytracer::materials::Metal;
 use rust_raytracer::materials::Glass;
 use rust_raytracer::camera::Camera;
+use rust_raytracer::scene::Scene;
 
 fn main() {
     let cam = Camera::new(
@@ -22,6 +25,17 @@ fn main() {
         10.0,
     );
 
+    let scene = match std::env::args().nth(1) {
+        Some(filename) => Scene::load(&filename).expect("err loading scene"),
+        None => Scene::new(random_scene()),
+    };
+
+    cam.render(&scene);
+
+    println!("Hello, world!");
+}
+
+fn random_scene() -> Vec<Sphere> {
     let mut hit_world: Vec<Sphere> = vec![];    
     let ground = Materials::Lambertian(
         Lambertian::new(
@@ -37,7 +51,7 @@ fn main() {
         ),
     );
 
-    let mut rng = rand::thread_rng();
+    let mut rng = SmallRng::seed_from_u64(0);
 
     for a in -11..11 {
         for b in -11..11 {
@@ -135,7 +149,5 @@ fn main() {
         ),
     );
 
-    cam.render(&hit_world);
-
-    println!("Hello, world!");
+    hit_world
 }
@@ -1,65 +1,219 @@
-use rand::Rng;
+use std::f64::consts::PI;
+use serde::{ Serialize, Deserialize };
 
 use crate::vec3::Vec3;
 use crate::ray::Ray;
 use crate::ray::HitRecord;
+use crate::ray::RayKind;
+use crate::texture::Textures;
+use crate::texture::Texture;
+use crate::thin_film::ThinFilm;
+use crate::medium::HomogeneousMedium;
+use crate::microfacet::TrowbridgeReitz;
+use crate::microfacet::Gtr1;
 use crate::utils::reflect;
 use crate::utils::refract;
 use crate::utils::reflectance;
+use crate::utils::fresnel_dielectric;
+use crate::utils::onb;
+use crate::sampler::PixelSampler;
 
-#[derive(Debug, Clone)]
+#[cfg(test)]
+use crate::utils::furnace;
+#[cfg(test)]
+use crate::utils::test_hit_record;
+#[cfg(test)]
+use assert_approx_eq::assert_approx_eq;
+#[cfg(test)]
+use crate::sampler::Samplers;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type")]
 pub enum Materials {
     Lambertian(Lambertian),
+    OrenNayar(OrenNayar),
     Metal(Metal),
     Glass(Glass),
+    Principled(Principled),
+    Coated(Coated),
+    Subsurface(Subsurface),
+    Cutout(Cutout),
+    Mix(Mix),
+    DiffuseLight(DiffuseLight),
 }
 
 pub trait Scatterable {
-    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<(Option<Ray>, Vec3)>;
+    fn scatter(&self, ray: &Ray, hit_record: &HitRecord, sampler: &mut PixelSampler) -> Option<(Option<Ray>, Vec3)>;
+
+    fn eval(&self, _ray: &Ray, _hit_record: &HitRecord, _wi: &Vec3) -> Vec3 {
+        Vec3::new(0.0, 0.0, 0.0)
+    }
+
+    fn scatter_pdf(&self, _ray: &Ray, _hit_record: &HitRecord, _wi: &Vec3) -> f64 {
+        0.0
+    }
+
+    fn scatter_with_pdf(&self, ray: &Ray, hit_record: &HitRecord, sampler: &mut PixelSampler) -> Option<(Option<Ray>, Vec3, f64)> {
+        let (scattered, attenuation) = self.scatter(ray, hit_record, sampler)?;
+        let pdf = scattered.map_or(0.0, |s| self.scatter_pdf(ray, hit_record, &s.dir.unit_vec()));
+
+        Some((scattered, attenuation, pdf))
+    }
+
+    fn lobe(&self, ray: &Ray, hit_record: &HitRecord, wi: &Vec3) -> RayKind {
+        lobe_kind(ray, hit_record, wi, RayKind::Specular)
+    }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Lambertian {
-    pub albedo: Vec3,
+    pub albedo: Textures,
 }
 
 impl Lambertian {
     pub fn new(albedo: Vec3) -> Lambertian {
+        Lambertian { albedo: albedo.into() }
+    }
+
+    pub fn textured(albedo: Textures) -> Lambertian {
         Lambertian { albedo }
     }
 }
 
 impl Scatterable for Lambertian {
-    fn scatter(&self, _ray: &Ray, hit_record: &HitRecord) -> Option<(Option<Ray>, Vec3)> {
-        let mut scatter_dir = hit_record.norm + Vec3::random_unit_vec();
+    fn scatter(&self, _ray: &Ray, hit_record: &HitRecord, sampler: &mut PixelSampler) -> Option<(Option<Ray>, Vec3)> {
+        let mut scatter_dir = hit_record.norm + Vec3::random_unit_vec(sampler);
         scatter_dir = if scatter_dir.near_zero() { hit_record.norm } else { scatter_dir };
         
         let scattered = Ray::new(hit_record.pt, scatter_dir);
-        let attenuation = self.albedo;
+        let attenuation = self.albedo.value(hit_record.u, hit_record.v, &hit_record.pt);
 
         Some((Some(scattered), attenuation))
     }
+
+    fn eval(&self, _ray: &Ray, hit_record: &HitRecord, wi: &Vec3) -> Vec3 {
+        let cos_theta = wi.dot(&hit_record.norm).max(0.0);
+
+        self.albedo.value(hit_record.u, hit_record.v, &hit_record.pt) * (cos_theta / PI)
+    }
+
+    fn scatter_pdf(&self, _ray: &Ray, hit_record: &HitRecord, wi: &Vec3) -> f64 {
+        wi.dot(&hit_record.norm).max(0.0) / PI
+    }
+
+    fn lobe(&self, ray: &Ray, hit_record: &HitRecord, wi: &Vec3) -> RayKind {
+        lobe_kind(ray, hit_record, wi, RayKind::Diffuse)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrenNayar {
+    pub albedo: Textures,
+    // Surface roughness as the standard deviation of facet slope angles, in degrees.
+    pub sigma: f64,
 }
 
-#[derive(Debug, Clone)]
+impl OrenNayar {
+    pub fn new(albedo: Vec3, sigma: f64) -> OrenNayar {
+        OrenNayar { albedo: albedo.into(), sigma }
+    }
+
+    pub fn textured(albedo: Textures, sigma: f64) -> OrenNayar {
+        OrenNayar { albedo, sigma }
+    }
+
+    fn reflectance(&self, wo: &Vec3, wi: &Vec3, n: &Vec3) -> f64 {
+        let sigma = self.sigma.to_radians();
+        let sigma2 = sigma * sigma;
+        let a = 1.0 - sigma2 / (2.0 * (sigma2 + 0.33));
+        let b = 0.45 * sigma2 / (sigma2 + 0.09);
+        let cos_i = wi.dot(n).clamp(0.0, 1.0);
+        let cos_o = wo.dot(n).clamp(0.0, 1.0);
+        let sin_i = (1.0 - cos_i * cos_i).sqrt();
+        let sin_o = (1.0 - cos_o * cos_o).sqrt();
+        let max_cos = if sin_i > 1e-4 && sin_o > 1e-4 {
+            let proj_i = (*wi - *n * cos_i) / sin_i;
+            let proj_o = (*wo - *n * cos_o) / sin_o;
+
+            proj_i.dot(&proj_o).max(0.0)
+        } else {
+            0.0
+        };
+        let (sin_alpha, tan_beta) = if cos_i > cos_o {
+            (sin_o, sin_i / cos_i.max(1e-4))
+        } else {
+            (sin_i, sin_o / cos_o.max(1e-4))
+        };
+
+        a + b * max_cos * sin_alpha * tan_beta
+    }
+}
+
+impl Scatterable for OrenNayar {
+    fn scatter(&self, ray: &Ray, hit_record: &HitRecord, sampler: &mut PixelSampler) -> Option<(Option<Ray>, Vec3)> {
+        let mut scatter_dir = hit_record.norm + Vec3::random_unit_vec(sampler);
+        scatter_dir = if scatter_dir.near_zero() { hit_record.norm } else { scatter_dir };
+
+        let wo = -ray.dir.unit_vec();
+        let wi = scatter_dir.unit_vec();
+        let scattered = Ray::new(hit_record.pt, scatter_dir);
+        let albedo = self.albedo.value(hit_record.u, hit_record.v, &hit_record.pt);
+
+        Some((Some(scattered), albedo * self.reflectance(&wo, &wi, &hit_record.norm)))
+    }
+
+    fn eval(&self, ray: &Ray, hit_record: &HitRecord, wi: &Vec3) -> Vec3 {
+        let cos_theta = wi.dot(&hit_record.norm);
+
+        if cos_theta <= 0.0 { return Vec3::new(0.0, 0.0, 0.0); }
+
+        let wo = -ray.dir.unit_vec();
+        let albedo = self.albedo.value(hit_record.u, hit_record.v, &hit_record.pt);
+
+        albedo * (self.reflectance(&wo, wi, &hit_record.norm) * cos_theta / PI)
+    }
+
+    fn scatter_pdf(&self, _ray: &Ray, hit_record: &HitRecord, wi: &Vec3) -> f64 {
+        wi.dot(&hit_record.norm).max(0.0) / PI
+    }
+
+    fn lobe(&self, ray: &Ray, hit_record: &HitRecord, wi: &Vec3) -> RayKind {
+        lobe_kind(ray, hit_record, wi, RayKind::Diffuse)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Metal {
     pub albedo: Vec3,
     pub fuzz: f64,
+    #[serde(default)]
+    pub film: Option<ThinFilm>,
 }
 
 impl Metal {
     pub fn new(albedo: Vec3, fuzz: f64) -> Metal {
-        Metal { albedo, fuzz }
+        Metal { albedo, fuzz, film: None }
+    }
+
+    pub fn with_film(self, film: ThinFilm) -> Metal {
+        Metal { film: Some(film), ..self }
     }
 }
 
 impl Scatterable for Metal {
-    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<(Option<Ray>, Vec3)> {
+    fn scatter(&self, ray: &Ray, hit_record: &HitRecord, sampler: &mut PixelSampler) -> Option<(Option<Ray>, Vec3)> {
         let mut reflected = reflect(&ray.dir, &hit_record.norm);
-        reflected = reflected.unit_vec() + Vec3::random_unit_vec() * self.fuzz;
+        reflected = reflected.unit_vec() + Vec3::random_unit_vec(sampler) * self.fuzz;
 
         let scattered = Ray::new(hit_record.pt, reflected);
-        let attenuation = self.albedo;
+        let attenuation = match &self.film {
+            Some(film) => {
+                let cos_theta = -ray.dir.unit_vec().dot(&hit_record.norm).min(1.0);
+
+                film.conductor_reflectance(cos_theta, self.albedo, hit_record)
+            }
+            None => self.albedo,
+        };
 
         if scattered.dir.dot(&hit_record.norm) > 0.0 { return Some((Some(scattered), attenuation)); }
         
@@ -67,47 +221,846 @@ impl Scatterable for Metal {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Glass {
     pub refract_idx: f64,
+    #[serde(default)]
+    pub film: Option<ThinFilm>,
 }
 
 impl Glass {
     pub fn new(refract_idx: f64) -> Glass {
-        Glass { refract_idx }
+        Glass { refract_idx, film: None }
+    }
+
+    pub fn with_film(self, film: ThinFilm) -> Glass {
+        Glass { film: Some(film), ..self }
     }
 }
 
 impl Scatterable for Glass {
-    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<(Option<Ray>, Vec3)> {
+    fn scatter(&self, ray: &Ray, hit_record: &HitRecord, sampler: &mut PixelSampler) -> Option<(Option<Ray>, Vec3)> {
         let attenuation = Vec3::new(1.0, 1.0,1.0);
         let ri = if hit_record.front_face { 1.0 / self.refract_idx } else { self.refract_idx };
         let dir_unit = ray.dir.unit_vec();
         let cos_theta = -dir_unit.dot(&hit_record.norm).min(1.0);
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
-        let cannot_refract = if ri * sin_theta > 1.0 { true } else { false };
-        let mut rng = rand::thread_rng();
+        let cannot_refract = ri * sin_theta > 1.0;
+        let (reflect_prob, reflect_attenuation, refract_attenuation) = match &self.film {
+            Some(film) => {
+                let (eta_i, eta_t) = if hit_record.front_face { (1.0, self.refract_idx) } else { (self.refract_idx, 1.0) };
+                let r = film.dielectric_reflectance(cos_theta, eta_i, eta_t, hit_record);
+                let p = ((r.x() + r.y() + r.z()) / 3.0).clamp(1e-4, 1.0 - 1e-4);
+
+                (p, r / p, (attenuation - r) / (1.0 - p))
+            }
+            None => (reflectance(cos_theta, ri), attenuation, attenuation),
+        };
         
-        if cannot_refract || reflectance(cos_theta, ri) > rng.gen::<f64>() {
+        if cannot_refract || reflect_prob > sampler.get_1d() {
             let dir = reflect(&dir_unit, &hit_record.norm);
             let scattered = Ray::new(hit_record.pt, dir);
 
-            return Some((Some(scattered), attenuation));
+            return Some((Some(scattered), if cannot_refract { attenuation } else { reflect_attenuation }));
         }
 
         let dir = refract(&dir_unit, &hit_record.norm, ri);
         let scattered = Ray::new(hit_record.pt, dir);
 
+        Some((Some(scattered), refract_attenuation))
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Principled {
+    pub base_color: Vec3,
+    pub metallic: f64,
+    pub roughness: f64,
+    pub specular: f64,
+    pub specular_tint: f64,
+    pub sheen: f64,
+    pub clearcoat: f64,
+    pub clearcoat_gloss: f64,
+    pub transmission: f64,
+    pub ior: f64,
+}
+
+impl Default for Principled {
+    fn default() -> Principled {
+        Principled::new(Vec3::new(0.8, 0.8, 0.8))
+    }
+}
+
+impl Principled {
+    pub fn new(base_color: Vec3) -> Principled {
+        Principled {
+            base_color,
+            metallic: 0.0,
+            roughness: 0.5,
+            specular: 0.5,
+            specular_tint: 0.0,
+            sheen: 0.0,
+            clearcoat: 0.0,
+            clearcoat_gloss: 1.0,
+            transmission: 0.0,
+            ior: 1.5,
+        }
+    }
+
+    fn local(&self, ray: &Ray, hit_record: &HitRecord, wi: &Vec3) -> (Vec3, Vec3, f64) {
+        let n = hit_record.norm;
+        let (t, b) = onb(&n);
+        let dir = -ray.dir.unit_vec();
+        let wo = Vec3::new(dir.dot(&t), dir.dot(&b), dir.dot(&n));
+        let wi = Vec3::new(wi.dot(&t), wi.dot(&b), wi.dot(&n));
+        let eta = if hit_record.front_face { self.ior } else { 1.0 / self.ior };
+
+        (wo, wi, eta)
+    }
+
+    fn lobe_weights(&self) -> [f64; 4] {
+        let strans = (1.0 - self.metallic) * self.transmission;
+        let weights = [
+            (1.0 - self.metallic) * (1.0 - self.transmission),
+            1.0 - strans,
+            0.25 * self.clearcoat,
+            strans,
+        ];
+        let sum: f64 = weights.iter().sum();
+
+        weights.map(|w| w / sum)
+    }
+
+    fn tint(&self) -> Vec3 {
+        let lum = luminance(&self.base_color);
+
+        if lum > 0.0 { self.base_color / lum } else { Vec3::new(1.0, 1.0, 1.0) }
+    }
+
+    fn clearcoat_distrib(&self) -> Gtr1 {
+        Gtr1::new(0.1 * (1.0 - self.clearcoat_gloss) + 0.001 * self.clearcoat_gloss)
+    }
+
+    fn f(&self, wo: &Vec3, wi: &Vec3, eta: f64) -> Vec3 {
+        let white = Vec3::new(1.0, 1.0, 1.0);
+        let cos_o = wo.z();
+        let cos_i = wi.z();
+        let strans = (1.0 - self.metallic) * self.transmission;
+        let spec = TrowbridgeReitz::new(self.roughness);
+
+        if cos_o == 0.0 || cos_i == 0.0 { return Vec3::new(0.0, 0.0, 0.0); }
+
+        if cos_i * cos_o > 0.0 {
+            let wh = *wo + *wi;
+
+            if wh.near_zero() { return Vec3::new(0.0, 0.0, 0.0); }
+
+            let wh = wh.unit_vec();
+            let cos_d = wi.dot(&wh);
+            let diffuse_w = (1.0 - self.metallic) * (1.0 - self.transmission);
+            let mut f = Vec3::new(0.0, 0.0, 0.0);
+
+            if diffuse_w > 0.0 {
+                let fd90 = 0.5 + 2.0 * self.roughness * cos_d * cos_d;
+                let fd = (1.0 + (fd90 - 1.0) * schlick_weight(cos_i.abs())) * (1.0 + (fd90 - 1.0) * schlick_weight(cos_o.abs()));
+                let sheen_color = (white + self.tint()) * 0.5;
+
+                f = f + self.base_color * (fd * diffuse_w / PI);
+                f = f + sheen_color * (self.sheen * schlick_weight(cos_d) * diffuse_w);
+            }
+
+            let cos_cos = 4.0 * (cos_o * cos_i).abs();
+
+            if strans < 1.0 {
+                let tinted = white * (1.0 - self.specular_tint) + self.tint() * self.specular_tint;
+                let c_spec0 = tinted * (self.specular * 0.08 * (1.0 - self.metallic)) + self.base_color * self.metallic;
+                let fr = c_spec0 + (white - c_spec0) * schlick_weight(cos_d);
+
+                f = f + fr * (spec.d(&wh) * spec.g(wo, wi) / cos_cos * (1.0 - strans));
+            }
+
+            if self.clearcoat > 0.0 {
+                let fr = 0.04 + 0.96 * schlick_weight(cos_d);
+                let g = TrowbridgeReitz { alpha: 0.25 }.g(wo, wi);
+
+                f = f + white * (0.25 * self.clearcoat * self.clearcoat_distrib().d(&wh) * g * fr / cos_cos);
+            }
+
+            if strans > 0.0 {
+                let fr = fresnel_dielectric(wo.dot(&wh), eta);
+
+                f = f + white * (strans * spec.d(&wh) * spec.g(wo, wi) * fr / cos_cos);
+            }
+
+            return f * cos_i.abs();
+        }
+
+        if strans <= 0.0 { return Vec3::new(0.0, 0.0, 0.0); }
+
+        let Some(wm) = transmission_half_vec(wo, wi, eta) else { return Vec3::new(0.0, 0.0, 0.0); };
+        let t = 1.0 - fresnel_dielectric(wo.dot(&wm), eta);
+        let denom = (wi.dot(&wm) + wo.dot(&wm) / eta).powi(2) * cos_i * cos_o;
+        let ft = t * spec.d(&wm) * spec.g(wo, wi) * (wi.dot(&wm) * wo.dot(&wm) / denom).abs() / (eta * eta);
+
+        self.base_color * (strans * ft * cos_i.abs())
+    }
+
+    fn pdf(&self, wo: &Vec3, wi: &Vec3, eta: f64) -> f64 {
+        let weights = self.lobe_weights();
+        let spec = TrowbridgeReitz::new(self.roughness);
+        let cos_i = wi.z();
+
+        if wo.z() * cos_i > 0.0 {
+            let wh = *wo + *wi;
+
+            if wh.near_zero() { return 0.0; }
+
+            let wh = wh.unit_vec();
+            let wo_dot_wh = 4.0 * wo.dot(&wh).abs();
+            let pdf_spec = spec.d_visible(wo, &wh) / wo_dot_wh;
+            let pdf_clearcoat = self.clearcoat_distrib().d(&wh) * wh.z().abs() / wo_dot_wh;
+            let fr = fresnel_dielectric(wo.dot(&wh), eta);
+
+            return weights[0] * cos_i.abs() / PI + weights[1] * pdf_spec + weights[2] * pdf_clearcoat + weights[3] * fr * pdf_spec;
+        }
+
+        if weights[3] <= 0.0 { return 0.0; }
+
+        let Some(wm) = transmission_half_vec(wo, wi, eta) else { return 0.0; };
+        let t = 1.0 - fresnel_dielectric(wo.dot(&wm), eta);
+        let dwm_dwi = wi.dot(&wm).abs() / (wi.dot(&wm) + wo.dot(&wm) / eta).powi(2);
+
+        weights[3] * spec.d_visible(wo, &wm) * dwm_dwi * t
+    }
+
+    fn sample(&self, wo: &Vec3, eta: f64, sampler: &mut PixelSampler) -> Option<Vec3> {
+        let weights = self.lobe_weights();
+        let mut uc = sampler.get_1d();
+        let u = sampler.get_2d();
+        let mut lobe = 0;
+
+        while lobe < 3 && uc >= weights[lobe] {
+            uc -= weights[lobe];
+            lobe += 1;
+        }
+
+        match lobe {
+            0 => {
+                let r = u.0.sqrt();
+                let phi = 2.0 * PI * u.1;
+
+                Some(Vec3::new(r * phi.cos(), r * phi.sin(), (1.0 - u.0).max(0.0).sqrt()))
+            }
+            1 => {
+                let wm = TrowbridgeReitz::new(self.roughness).sample_wm(wo, u);
+
+                Some(reflect(&-*wo, &wm))
+            }
+            2 => {
+                let wm = self.clearcoat_distrib().sample_wm(u);
+
+                Some(reflect(&-*wo, &wm))
+            }
+            _ => {
+                let wm = TrowbridgeReitz::new(self.roughness).sample_wm(wo, u);
+                let cos_theta = wo.dot(&wm);
+
+                if sampler.get_1d() < fresnel_dielectric(cos_theta, eta) {
+                    return Some(reflect(&-*wo, &wm));
+                }
+
+                if (1.0 - cos_theta * cos_theta) / (eta * eta) >= 1.0 { return None; }
+
+                Some(refract(&-*wo, &wm, 1.0 / eta))
+            }
+        }
+    }
+}
+
+impl Scatterable for Principled {
+    fn scatter(&self, ray: &Ray, hit_record: &HitRecord, sampler: &mut PixelSampler) -> Option<(Option<Ray>, Vec3)> {
+        let n = hit_record.norm;
+        let (t, b) = onb(&n);
+        let dir = -ray.dir.unit_vec();
+        let wo = Vec3::new(dir.dot(&t), dir.dot(&b), dir.dot(&n));
+        let eta = if hit_record.front_face { self.ior } else { 1.0 / self.ior };
+        let wi = self.sample(&wo, eta, sampler)?;
+        let pdf = self.pdf(&wo, &wi, eta);
+
+        if pdf <= 0.0 || wi.z() == 0.0 { return None; }
+
+        let attenuation = self.f(&wo, &wi, eta) / pdf;
+        let scattered = Ray::new(hit_record.pt, t * wi.x() + b * wi.y() + n * wi.z());
+
+        Some((Some(scattered), attenuation))
+    }
+
+    fn eval(&self, ray: &Ray, hit_record: &HitRecord, wi: &Vec3) -> Vec3 {
+        let (wo, wi, eta) = self.local(ray, hit_record, wi);
+
+        self.f(&wo, &wi, eta)
+    }
+
+    fn scatter_pdf(&self, ray: &Ray, hit_record: &HitRecord, wi: &Vec3) -> f64 {
+        let (wo, wi, eta) = self.local(ray, hit_record, wi);
+
+        self.pdf(&wo, &wi, eta)
+    }
+
+    fn lobe(&self, ray: &Ray, hit_record: &HitRecord, wi: &Vec3) -> RayKind {
+        let (wo, wi_local, eta) = self.local(ray, hit_record, wi);
+        let diffuse = self.lobe_weights()[0] * wi_local.z().abs() / PI;
+        let reflected = if diffuse >= 0.5 * self.pdf(&wo, &wi_local, eta) { RayKind::Diffuse } else { RayKind::Specular };
+
+        lobe_kind(ray, hit_record, wi, reflected)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Coated {
+    pub base: Box<Materials>,
+    pub ior: f64,
+    #[serde(default)]
+    pub thickness: f64,
+    #[serde(default = "no_absorption")]
+    pub absorption: Vec3,
+}
+
+impl Coated {
+    const MAX_INTERNAL_BOUNCES: usize = 32;
+
+    pub fn new(base: Materials, ior: f64, thickness: f64, absorption: Vec3) -> Coated {
+        Coated { base: Box::new(base), ior, thickness, absorption }
+    }
+
+    fn transmittance(&self, cos_theta: f64) -> Vec3 {
+        let dist = self.thickness / cos_theta.abs().max(1e-4);
+
+        Vec3::new(
+            (-self.absorption.x() * dist).exp(),
+            (-self.absorption.y() * dist).exp(),
+            (-self.absorption.z() * dist).exp(),
+        )
+    }
+
+    // Maps outer directions to the ones seen by the base and returns both interface transmittances times the
+    // solid angle compression cos_i / (ior^2 cos_i'), which also carries the 1/ior^2 radiance scaling on exit.
+    fn inner(&self, ray: &Ray, hit_record: &HitRecord, wi: &Vec3) -> Option<(Ray, Vec3, f64)> {
+        let n = hit_record.norm;
+        let dir_unit = ray.dir.unit_vec();
+        let wi = wi.unit_vec();
+        let cos_o = -dir_unit.dot(&n);
+        let cos_i = wi.dot(&n);
+
+        if cos_o <= 0.0 || cos_i <= 0.0 { return None; }
+
+        let inner_ray = Ray::new(hit_record.pt, refract(&dir_unit, &n, 1.0 / self.ior));
+        let inner_wi = -refract(&-wi, &n, 1.0 / self.ior);
+        let transmitted = (1.0 - fresnel_dielectric(cos_o, self.ior)) * (1.0 - fresnel_dielectric(cos_i, self.ior));
+
+        Some((inner_ray, inner_wi, transmitted * cos_i / (self.ior * self.ior * inner_wi.dot(&n))))
+    }
+}
+
+impl Scatterable for Coated {
+    fn scatter(&self, ray: &Ray, hit_record: &HitRecord, sampler: &mut PixelSampler) -> Option<(Option<Ray>, Vec3)> {
+        let (scattered, attenuation, _) = self.scatter_with_pdf(ray, hit_record, sampler)?;
+
+        Some((scattered, attenuation))
+    }
+
+    fn scatter_with_pdf(&self, ray: &Ray, hit_record: &HitRecord, sampler: &mut PixelSampler) -> Option<(Option<Ray>, Vec3, f64)> {
+        if !hit_record.front_face { return self.base.scatter_with_pdf(ray, hit_record, sampler); }
+
+        let n = hit_record.norm;
+        let dir_unit = ray.dir.unit_vec();
+        let cos_theta = -dir_unit.dot(&n).min(1.0);
+
+        if sampler.get_1d() < fresnel_dielectric(cos_theta, self.ior) {
+            let scattered = Ray::new(hit_record.pt, reflect(&dir_unit, &n));
+
+            return Some((Some(scattered), Vec3::new(1.0, 1.0, 1.0), 0.0));
+        }
+
+        let mut dir = refract(&dir_unit, &n, 1.0 / self.ior);
+        let mut attenuation = Vec3::new(1.0, 1.0, 1.0);
+
+        // Only paths that leave the coating after a single base bounce match eval, so they alone carry a pdf for MIS.
+        for bounce in 0..Coated::MAX_INTERNAL_BOUNCES {
+            attenuation = attenuation * self.transmittance(dir.dot(&n));
+
+            let (scattered, albedo) = self.base.scatter(&Ray::new(hit_record.pt, dir), hit_record, sampler)?;
+            let Some(scattered) = scattered else { return Some((None, attenuation * albedo, 0.0)); };
+
+            attenuation = attenuation * albedo;
+            dir = scattered.dir.unit_vec();
+
+            if dir.dot(&n) <= 0.0 { return Some((Some(Ray::new(hit_record.pt, dir)), attenuation, 0.0)); }
+
+            attenuation = attenuation * self.transmittance(dir.dot(&n));
+
+            let fresnel = fresnel_dielectric(dir.dot(&n), 1.0 / self.ior);
+
+            if fresnel >= 1.0 || (bounce + 1 < Coated::MAX_INTERNAL_BOUNCES && sampler.get_1d() < fresnel) {
+                dir = reflect(&dir, &n);
+                continue;
+            }
+
+            let exit = refract(&dir, &-n, self.ior);
+            let pdf = if bounce == 0 { self.scatter_pdf(ray, hit_record, &exit) } else { 0.0 };
+
+            return Some((Some(Ray::new(hit_record.pt, exit)), attenuation, pdf));
+        }
+
+        None
+    }
+
+    fn eval(&self, ray: &Ray, hit_record: &HitRecord, wi: &Vec3) -> Vec3 {
+        if !hit_record.front_face { return self.base.eval(ray, hit_record, wi); }
+
+        let Some((inner_ray, inner_wi, scale)) = self.inner(ray, hit_record, wi) else { return Vec3::new(0.0, 0.0, 0.0); };
+        let n = hit_record.norm;
+        let absorption = self.transmittance(inner_ray.dir.dot(&n)) * self.transmittance(inner_wi.dot(&n));
+
+        self.base.eval(&inner_ray, hit_record, &inner_wi) * absorption * scale
+    }
+
+    fn scatter_pdf(&self, ray: &Ray, hit_record: &HitRecord, wi: &Vec3) -> f64 {
+        if !hit_record.front_face { return self.base.scatter_pdf(ray, hit_record, wi); }
+
+        let Some((inner_ray, inner_wi, scale)) = self.inner(ray, hit_record, wi) else { return 0.0; };
+
+        self.base.scatter_pdf(&inner_ray, hit_record, &inner_wi) * scale
+    }
+
+    fn lobe(&self, ray: &Ray, hit_record: &HitRecord, wi: &Vec3) -> RayKind {
+        let mirror = reflect(&ray.dir.unit_vec(), &hit_record.norm);
+
+        if hit_record.front_face && (wi.unit_vec() - mirror).len_sqr() < 1e-12 { return RayKind::Specular; }
+
+        self.base.lobe(ray, hit_record, wi)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Subsurface {
+    pub albedo: Vec3,
+    pub mfp: Vec3,
+    pub ior: f64,
+    #[serde(default)]
+    pub g: f64,
+}
+
+impl Subsurface {
+    pub fn new(albedo: Vec3, mfp: Vec3, ior: f64) -> Subsurface {
+        Subsurface { albedo, mfp, ior, g: 0.0 }
+    }
+
+    pub fn medium(&self) -> HomogeneousMedium {
+        let sigma_t = Vec3::new(1.0 / self.mfp.x(), 1.0 / self.mfp.y(), 1.0 / self.mfp.z());
+
+        HomogeneousMedium::new(sigma_t, self.albedo, self.g)
+    }
+}
+
+impl Scatterable for Subsurface {
+    fn scatter(&self, ray: &Ray, hit_record: &HitRecord, sampler: &mut PixelSampler) -> Option<(Option<Ray>, Vec3)> {
+        let dir_unit = ray.dir.unit_vec();
+        let cos_theta = -dir_unit.dot(&hit_record.norm).min(1.0);
+        let eta = if hit_record.front_face { self.ior } else { 1.0 / self.ior };
+        let attenuation = Vec3::new(1.0, 1.0, 1.0);
+
+        if sampler.get_1d() < fresnel_dielectric(cos_theta, eta) {
+            let scattered = Ray::new(hit_record.pt, reflect(&dir_unit, &hit_record.norm));
+
+            return Some((Some(scattered), attenuation));
+        }
+
+        let scattered = Ray::new(hit_record.pt, refract(&dir_unit, &hit_record.norm, 1.0 / eta));
+
         Some((Some(scattered), attenuation))
     }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Cutout {
+    pub material: Box<Materials>,
+    pub alpha: Textures,
+    #[serde(default = "default_alpha_threshold")]
+    pub threshold: f64,
+    #[serde(default)]
+    pub stochastic: bool,
+}
+
+impl Cutout {
+    pub fn new(material: Materials, alpha: Textures, threshold: f64) -> Cutout {
+        Cutout { material: Box::new(material), alpha, threshold, stochastic: false }
+    }
+
+    pub fn stochastic(material: Materials, alpha: Textures) -> Cutout {
+        Cutout { material: Box::new(material), alpha, threshold: 0.0, stochastic: true }
+    }
+
+    pub fn opacity(&self, hit_record: &HitRecord) -> f64 {
+        let alpha = self.alpha.value(hit_record.u, hit_record.v, &hit_record.pt).x();
+
+        if self.stochastic { return alpha.clamp(0.0, 1.0); }
+
+        if alpha < self.threshold { 0.0 } else { 1.0 }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Mix {
+    pub a: Box<Materials>,
+    pub b: Box<Materials>,
+    pub amount: Textures,
+}
+
+impl Mix {
+    pub fn new(a: Materials, b: Materials, amount: Textures) -> Mix {
+        Mix { a: Box::new(a), b: Box::new(b), amount }
+    }
+
+    pub fn choose(&self, u: f64, hit_record: &HitRecord) -> &Materials {
+        let weight = self.weight(hit_record);
+        let (side, u) = if u < weight { (&*self.b, u / weight) } else { (&*self.a, (u - weight) / (1.0 - weight)) };
+
+        match side {
+            Materials::Mix(m) => m.choose(u, hit_record),
+            _ => side,
+        }
+    }
+
+    pub fn opacity(&self, hit_record: &HitRecord) -> f64 {
+        let amount = self.amount.value(hit_record.u, hit_record.v, &hit_record.pt).x();
+
+        (1.0 - amount) * self.a.opacity(hit_record) + amount * self.b.opacity(hit_record)
+    }
+
+    // Probability of picking b given that the surface was not cut out at this point.
+    fn weight(&self, hit_record: &HitRecord) -> f64 {
+        let amount = self.amount.value(hit_record.u, hit_record.v, &hit_record.pt).x();
+        let a = (1.0 - amount) * self.a.opacity(hit_record);
+        let b = amount * self.b.opacity(hit_record);
+
+        if a + b > 0.0 { b / (a + b) } else { amount }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DiffuseLight {
+    pub emit: Textures,
+}
+
+impl DiffuseLight {
+    pub fn new(emit: Vec3) -> DiffuseLight {
+        DiffuseLight { emit: emit.into() }
+    }
+
+    pub fn textured(emit: Textures) -> DiffuseLight {
+        DiffuseLight { emit }
+    }
+}
+
+impl Scatterable for DiffuseLight {
+    fn scatter(&self, _ray: &Ray, hit_record: &HitRecord, _sampler: &mut PixelSampler) -> Option<(Option<Ray>, Vec3)> {
+        Some((None, self.emit.value(hit_record.u, hit_record.v, &hit_record.pt)))
+    }
+}
+
+fn default_alpha_threshold() -> f64 {
+    0.5
+}
+
+fn no_absorption() -> Vec3 {
+    Vec3::new(0.0, 0.0, 0.0)
+}
+
+fn transmission_half_vec(wo: &Vec3, wi: &Vec3, eta: f64) -> Option<Vec3> {
+    let wm = *wi * eta + *wo;
+
+    if wm.near_zero() { return None; }
+
+    let wm = if wm.z() < 0.0 { -wm.unit_vec() } else { wm.unit_vec() };
+
+    if wm.dot(wi) * wi.z() < 0.0 || wm.dot(wo) * wo.z() < 0.0 { return None; }
+
+    Some(wm)
+}
+
+fn schlick_weight(cos_theta: f64) -> f64 {
+    (1.0 - cos_theta).clamp(0.0, 1.0).powi(5)
+}
+
+fn lobe_kind(ray: &Ray, hit_record: &HitRecord, wi: &Vec3, reflected: RayKind) -> RayKind {
+    if wi.dot(&hit_record.geo_norm) * ray.dir.dot(&hit_record.geo_norm) > 0.0 { return RayKind::Transmission; }
+
+    reflected
+}
+
+pub fn luminance(color: &Vec3) -> f64 {
+    0.2126 * color.x() + 0.7152 * color.y() + 0.0722 * color.z()
+}
+
+impl Materials {
+    const HASH_CELL: f64 = 1e-6;
+
+    // A Mix has no single interior, so callers resolve it with `resolve` first.
+    pub fn interior(&self) -> Option<HomogeneousMedium> {
+        match self {
+            Materials::Subsurface(s) => Some(s.medium()),
+            Materials::Cutout(c) => c.material.interior(),
+            _ => None,
+        }
+    }
+
+    pub fn resolve(&self, u: f64, hit_record: &HitRecord) -> &Materials {
+        match self {
+            Materials::Mix(m) => m.choose(u, hit_record),
+            _ => self,
+        }
+    }
+
+    pub fn opacity(&self, hit_record: &HitRecord) -> f64 {
+        match self {
+            Materials::Cutout(c) => c.opacity(hit_record),
+            Materials::Mix(m) => m.opacity(hit_record),
+            _ => 1.0,
+        }
+    }
+
+    pub fn is_cut_out(&self, hit_record: &HitRecord, sampler: &PixelSampler) -> bool {
+        let opacity = self.opacity(hit_record);
+
+        if opacity >= 1.0 { return false; }
+        if opacity <= 0.0 { return true; }
+
+        // Quantize the point so shadow and camera rays reaching it from different origins make the same choice.
+        let p = hit_record.pt / Materials::HASH_CELL;
+
+        opacity <= sampler.hash_1d(&[p.x().round(), p.y().round(), p.z().round()])
+    }
+}
+
 impl Scatterable for Materials {
-    fn scatter(&self, ray: &Ray, hit_record: &HitRecord) -> Option<(Option<Ray>, Vec3)> {
+    fn scatter(&self, ray: &Ray, hit_record: &HitRecord, sampler: &mut PixelSampler) -> Option<(Option<Ray>, Vec3)> {
         match self {
-            Materials::Lambertian(l) => { l.scatter(ray, hit_record) }
-            Materials::Metal(m) => { m.scatter(ray, hit_record) }
-            Materials::Glass(g) => { g.scatter(ray, hit_record) }
+            Materials::Lambertian(l) => { l.scatter(ray, hit_record, sampler) }
+            Materials::OrenNayar(o) => { o.scatter(ray, hit_record, sampler) }
+            Materials::Metal(m) => { m.scatter(ray, hit_record, sampler) }
+            Materials::Glass(g) => { g.scatter(ray, hit_record, sampler) }
+            Materials::Principled(p) => { p.scatter(ray, hit_record, sampler) }
+            Materials::Coated(c) => { c.scatter(ray, hit_record, sampler) }
+            Materials::Subsurface(s) => { s.scatter(ray, hit_record, sampler) }
+            Materials::Cutout(c) => { c.material.scatter(ray, hit_record, sampler) }
+            Materials::Mix(m) => { m.choose(sampler.get_1d(), hit_record).scatter(ray, hit_record, sampler) }
+            Materials::DiffuseLight(d) => { d.scatter(ray, hit_record, sampler) }
+        }
+    }
+
+    fn eval(&self, ray: &Ray, hit_record: &HitRecord, wi: &Vec3) -> Vec3 {
+        match self {
+            Materials::Lambertian(l) => { l.eval(ray, hit_record, wi) }
+            Materials::OrenNayar(o) => { o.eval(ray, hit_record, wi) }
+            Materials::Principled(p) => { p.eval(ray, hit_record, wi) }
+            Materials::Coated(c) => { c.eval(ray, hit_record, wi) }
+            Materials::Cutout(c) => { c.material.eval(ray, hit_record, wi) }
+            Materials::Mix(m) => {
+                let weight = m.weight(hit_record);
+
+                m.a.eval(ray, hit_record, wi) * (1.0 - weight) + m.b.eval(ray, hit_record, wi) * weight
+            }
+            _ => { Vec3::new(0.0, 0.0, 0.0) }
+        }
+    }
+
+    fn scatter_pdf(&self, ray: &Ray, hit_record: &HitRecord, wi: &Vec3) -> f64 {
+        match self {
+            Materials::Lambertian(l) => { l.scatter_pdf(ray, hit_record, wi) }
+            Materials::OrenNayar(o) => { o.scatter_pdf(ray, hit_record, wi) }
+            Materials::Principled(p) => { p.scatter_pdf(ray, hit_record, wi) }
+            Materials::Coated(c) => { c.scatter_pdf(ray, hit_record, wi) }
+            Materials::Cutout(c) => { c.material.scatter_pdf(ray, hit_record, wi) }
+            Materials::Mix(m) => {
+                let weight = m.weight(hit_record);
+
+                m.a.scatter_pdf(ray, hit_record, wi) * (1.0 - weight) + m.b.scatter_pdf(ray, hit_record, wi) * weight
+            }
+            _ => { 0.0 }
+        }
+    }
+
+    fn scatter_with_pdf(&self, ray: &Ray, hit_record: &HitRecord, sampler: &mut PixelSampler) -> Option<(Option<Ray>, Vec3, f64)> {
+        match self {
+            Materials::Coated(c) => { c.scatter_with_pdf(ray, hit_record, sampler) }
+            Materials::Cutout(c) => { c.material.scatter_with_pdf(ray, hit_record, sampler) }
+            Materials::Mix(m) => { m.choose(sampler.get_1d(), hit_record).scatter_with_pdf(ray, hit_record, sampler) }
+            _ => {
+                let (scattered, attenuation) = self.scatter(ray, hit_record, sampler)?;
+                let pdf = scattered.map_or(0.0, |s| self.scatter_pdf(ray, hit_record, &s.dir.unit_vec()));
+
+                Some((scattered, attenuation, pdf))
+            }
+        }
+    }
+
+    fn lobe(&self, ray: &Ray, hit_record: &HitRecord, wi: &Vec3) -> RayKind {
+        match self {
+            Materials::Lambertian(l) => { l.lobe(ray, hit_record, wi) }
+            Materials::OrenNayar(o) => { o.lobe(ray, hit_record, wi) }
+            Materials::Principled(p) => { p.lobe(ray, hit_record, wi) }
+            Materials::Coated(c) => { c.lobe(ray, hit_record, wi) }
+            Materials::Cutout(c) => { c.material.lobe(ray, hit_record, wi) }
+            Materials::Mix(m) => {
+                let weight = m.weight(hit_record);
+
+                if m.b.scatter_pdf(ray, hit_record, wi) * weight > m.a.scatter_pdf(ray, hit_record, wi) * (1.0 - weight) { return m.b.lobe(ray, hit_record, wi); }
+
+                m.a.lobe(ray, hit_record, wi)
+            }
+            _ => { lobe_kind(ray, hit_record, wi, RayKind::Specular) }
         }
     }
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_principled_furnace() {
+    let mut metal = Principled::new(Vec3::new(1.0, 1.0, 1.0));
+    let mut glass = Principled::new(Vec3::new(1.0, 1.0, 1.0));
+
+    metal.metallic = 1.0;
+    metal.roughness = 0.2;
+    glass.transmission = 1.0;
+    glass.roughness = 0.1;
+
+    let fresnel = fresnel_dielectric(Vec3::new(0.3, 0.2, 1.0).unit_vec().z(), glass.ior);
+    let expected = fresnel + (1.0 - fresnel) / (glass.ior * glass.ior);
+    let metal_albedo = furnace(&Materials::Principled(metal), 20000);
+    let glass_albedo = furnace(&Materials::Principled(glass), 20000);
+
+    assert!(metal_albedo.x() > 0.9 && metal_albedo.x() < 1.01);
+    assert_approx_eq!(glass_albedo.x(), expected, 0.03);
+}
+
+#[test]
+fn test_coated_furnace() {
+    let white = Materials::Lambertian(Lambertian::new(Vec3::new(1.0, 1.0, 1.0)));
+    let clear = Coated::new(white.clone(), 1.5, 0.0, Vec3::new(0.0, 0.0, 0.0));
+    let tinted = Coated::new(white, 1.5, 0.1, Vec3::new(0.5, 1.0, 2.0));
+
+    let clear_albedo = furnace(&Materials::Coated(clear), 20000);
+    let tinted_albedo = furnace(&Materials::Coated(tinted), 20000);
+
+    assert_approx_eq!(clear_albedo.x(), 1.0, 1e-9);
+    assert!(tinted_albedo.x() > tinted_albedo.y() && tinted_albedo.y() > tinted_albedo.z());
+}
+
+#[test]
+fn test_coated_eval_matches_scatter() {
+    let base = Materials::Lambertian(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)));
+    let coated = Materials::Coated(Coated::new(base, 1.5, 0.1, Vec3::new(1.0, 1.0, 1.0)));
+    let ray = Ray::new(Vec3::new(0.3, 0.2, 1.0), Vec3::new(-0.3, -0.2, -1.0));
+    let hit_record = test_hit_record(&coated);
+    let mut sampler = PixelSampler::new(Samplers::default(), 0, (0, 0), 0);
+    let n = 50000;
+    let (mut sampled, mut integrated) = (0.0, 0.0);
+
+    for _ in 0..n {
+        if let Some((Some(scattered), attenuation, pdf)) = coated.scatter_with_pdf(&ray, &hit_record, &mut sampler) {
+            if pdf > 0.0 {
+                let wi = scattered.dir.unit_vec();
+
+                assert_approx_eq!(attenuation.x(), coated.eval(&ray, &hit_record, &wi).x() / pdf, 1e-9);
+                assert_approx_eq!(pdf, coated.scatter_pdf(&ray, &hit_record, &wi), 1e-9);
+
+                sampled += attenuation.x() / (n as f64);
+            }
+        }
+
+        let wi = Vec3::random_on_hemisphere(&hit_record.norm, &mut sampler);
+
+        integrated += coated.eval(&ray, &hit_record, &wi).x() * 2.0 * PI / (n as f64);
+    }
+
+    assert!(sampled > 0.1);
+    assert_approx_eq!(sampled, integrated, 0.01);
+}
+
+#[test]
+fn test_oren_nayar() {
+    let smooth = Materials::OrenNayar(OrenNayar::new(Vec3::new(0.5, 0.5, 0.5), 0.0));
+    let rough = Materials::OrenNayar(OrenNayar::new(Vec3::new(1.0, 1.0, 1.0), 30.0));
+
+    let smooth_albedo = furnace(&smooth, 1000);
+    let rough_albedo = furnace(&rough, 20000);
+
+    assert_approx_eq!(smooth_albedo.x(), 0.5, 1e-9);
+    assert!(rough_albedo.x() > 0.7 && rough_albedo.x() < 1.0);
+}
+
+#[test]
+fn test_mix() {
+    let black = Materials::Lambertian(Lambertian::new(Vec3::new(0.0, 0.0, 0.0)));
+    let white = Materials::Lambertian(Lambertian::new(Vec3::new(1.0, 1.0, 1.0)));
+    let nested = Materials::Mix(Mix::new(black.clone(), white.clone(), Vec3::new(1.0, 1.0, 1.0).into()));
+    let mix = Materials::Mix(Mix::new(black, nested, Vec3::new(0.3, 0.3, 0.3).into()));
+    let mut count = 0;
+    let hit_record = test_hit_record(&mix);
+    let n = 20000;
+    let mut sampler = PixelSampler::new(Samplers::default(), 0, (0, 0), 0);
+
+    for i in 0..n {
+        let ray = Ray::new(Vec3::new(i as f64, 0.0, 1.0), Vec3::new(-(i as f64), 0.0, -1.0));
+
+        if let Some((_, attenuation)) = mix.scatter(&ray, &hit_record, &mut sampler) {
+            count += attenuation.x() as usize;
+        }
+    }
+
+    assert_approx_eq!((count as f64) / (n as f64), 0.3, 0.02);
+}
+
+#[test]
+fn test_nested_mix() {
+    let leaf = |albedo: Vec3| Materials::Lambertian(Lambertian::new(albedo));
+    let half = || -> Textures { Vec3::new(0.5, 0.5, 0.5).into() };
+    let left = Materials::Mix(Mix::new(leaf(Vec3::new(1.0, 0.0, 0.0)), leaf(Vec3::new(0.0, 1.0, 0.0)), half()));
+    let right = Materials::Mix(Mix::new(leaf(Vec3::new(0.0, 0.0, 1.0)), leaf(Vec3::new(0.0, 0.0, 0.0)), half()));
+    let mix = Materials::Mix(Mix::new(left, right, half()));
+    let hit_record = test_hit_record(&mix);
+    let mut counts = Vec3::new(0.0, 0.0, 0.0);
+    let n = 20000;
+    let mut sampler = PixelSampler::new(Samplers::default(), 0, (0, 0), 0);
+
+    for i in 0..n {
+        let ray = Ray::new(Vec3::new(i as f64, 0.0, 1.0), Vec3::new(-(i as f64), 0.0, -1.0));
+
+        if let Some((_, attenuation)) = mix.scatter(&ray, &hit_record, &mut sampler) {
+            counts = counts + attenuation;
+        }
+    }
+
+    let counts = counts / (n as f64);
+
+    assert_approx_eq!(counts.x(), 0.25, 0.02);
+    assert_approx_eq!(counts.y(), 0.25, 0.02);
+    assert_approx_eq!(counts.z(), 0.25, 0.02);
+}
+
+#[test]
+fn test_mix_resolve() {
+    let white = Materials::Lambertian(Lambertian::new(Vec3::new(1.0, 1.0, 1.0)));
+    let subsurface = Materials::Subsurface(Subsurface::new(Vec3::new(1.0, 1.0, 1.0), Vec3::new(0.1, 0.1, 0.1), 1.3));
+    let clear = Materials::Cutout(Cutout::new(white.clone(), Vec3::new(0.0, 0.0, 0.0).into(), 0.5));
+    let mix = Materials::Mix(Mix::new(subsurface, white.clone(), Vec3::new(0.5, 0.5, 0.5).into()));
+    let holes = Materials::Mix(Mix::new(clear, white, Vec3::new(0.5, 0.5, 0.5).into()));
+    let hit_record = test_hit_record(&mix);
+
+    assert!(mix.interior().is_none());
+    assert!(mix.resolve(0.25, &hit_record).interior().is_none());
+    assert!(mix.resolve(0.75, &hit_record).interior().is_some());
+    assert_approx_eq!(holes.opacity(&hit_record), 0.5);
+    assert!(matches!(holes.resolve(0.9, &hit_record), Materials::Lambertian(_)));
+}
@@ -0,0 +1,129 @@
+use std::f64::consts::PI;
+
+use crate::vec3::Vec3;
+use crate::ray::Ray;
+use crate::utils::onb;
+use crate::sampler::PixelSampler;
+
+#[cfg(test)]
+use assert_approx_eq::assert_approx_eq;
+#[cfg(test)]
+use crate::sampler::Samplers;
+
+#[derive(Debug, Clone, Copy)]
+pub struct HomogeneousMedium {
+    pub sigma_t: Vec3,
+    pub albedo: Vec3,
+    pub g: f64,
+}
+
+pub enum MediumEvent {
+    Scatter(Ray, Vec3),
+    Pass(Vec3),
+}
+
+impl HomogeneousMedium {
+    pub fn new(sigma_t: Vec3, albedo: Vec3, g: f64) -> HomogeneousMedium {
+        HomogeneousMedium { sigma_t, albedo, g }
+    }
+
+    pub fn transmittance(&self, dist: f64) -> Vec3 {
+        Vec3::new(
+            (-self.sigma_t.x() * dist).exp(),
+            (-self.sigma_t.y() * dist).exp(),
+            (-self.sigma_t.z() * dist).exp(),
+        )
+    }
+
+    pub fn sample(&self, ray: &Ray, t_max: f64, sampler: &mut PixelSampler) -> MediumEvent {
+        let dir = ray.dir.unit_vec();
+        let seg_len = t_max * ray.dir.len();
+        let sigma = [self.sigma_t.x(), self.sigma_t.y(), self.sigma_t.z()];
+        let sigma_c = sigma[((sampler.get_1d() * 3.0) as usize).min(2)];
+        let dist = if sigma_c > 0.0 { -(1.0 - sampler.get_1d()).ln() / sigma_c } else { f64::INFINITY };
+
+        if dist < seg_len {
+            let tr = self.transmittance(dist);
+            let density = tr * self.sigma_t;
+            let pdf = (density.x() + density.y() + density.z()) / 3.0;
+            let weight = density * self.albedo / pdf;
+            let scattered = Ray::new(ray.orig + dir * dist, self.sample_phase(&dir, sampler));
+
+            return MediumEvent::Scatter(scattered, weight);
+        }
+
+        let tr = self.transmittance(seg_len);
+        let pdf = (tr.x() + tr.y() + tr.z()) / 3.0;
+
+        MediumEvent::Pass(tr / pdf)
+    }
+
+    pub fn phase(&self, dir: &Vec3, wi: &Vec3) -> f64 {
+        let cos_theta = dir.dot(&wi.unit_vec());
+        let denom = 1.0 + self.g * self.g - 2.0 * self.g * cos_theta;
+
+        (1.0 - self.g * self.g) / (4.0 * PI * denom * denom.sqrt())
+    }
+
+    pub fn sample_phase(&self, dir: &Vec3, sampler: &mut PixelSampler) -> Vec3 {
+        let u = sampler.get_1d();
+        let cos_theta = if self.g.abs() < 1e-3 {
+            1.0 - 2.0 * u
+        } else {
+            let sqr = (1.0 - self.g * self.g) / (1.0 - self.g + 2.0 * self.g * u);
+
+            ((1.0 + self.g * self.g - sqr * sqr) / (2.0 * self.g)).clamp(-1.0, 1.0)
+        };
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * sampler.get_1d();
+        let (t, b) = onb(dir);
+
+        t * (sin_theta * phi.cos()) + b * (sin_theta * phi.sin()) + *dir * cos_theta
+    }
+}
+
+#[test]
+fn test_phase_mean_cosine() {
+    let medium = HomogeneousMedium::new(Vec3::new(1.0, 1.0, 1.0), Vec3::new(1.0, 1.0, 1.0), 0.6);
+    let dir = Vec3::new(0.0, 1.0, 0.0);
+    let n = 50000;
+    let mut mean = 0.0;
+    let mut sampler = PixelSampler::new(Samplers::default(), 0, (0, 0), 0);
+
+    for _ in 0..n {
+        mean += medium.sample_phase(&dir, &mut sampler).dot(&dir) / (n as f64);
+    }
+
+    assert_approx_eq!(mean, 0.6, 0.02);
+}
+
+#[test]
+fn test_phase_normalized() {
+    let medium = HomogeneousMedium::new(Vec3::new(1.0, 1.0, 1.0), Vec3::new(1.0, 1.0, 1.0), 0.6);
+    let dir = Vec3::new(0.0, 1.0, 0.0);
+    let n = 10000;
+    let integral: f64 = (0..n).map(|i| {
+        let cos_theta = -1.0 + 2.0 * (i as f64 + 0.5) / (n as f64);
+        let wi = Vec3::new((1.0 - cos_theta * cos_theta).sqrt(), cos_theta, 0.0);
+
+        medium.phase(&dir, &wi) * 2.0 * PI * 2.0 / (n as f64)
+    }).sum();
+
+    assert_approx_eq!(integral, 1.0, 1e-3);
+}
+
+#[test]
+fn test_white_medium_conserves_energy() {
+    let medium = HomogeneousMedium::new(Vec3::new(2.0, 2.0, 2.0), Vec3::new(1.0, 1.0, 1.0), 0.0);
+    let ray = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+    let mut sampler = PixelSampler::new(Samplers::default(), 0, (0, 0), 0);
+
+    for _ in 0..100 {
+        let weight = match medium.sample(&ray, 1.0, &mut sampler) {
+            MediumEvent::Scatter(_, weight) => weight,
+            MediumEvent::Pass(weight) => weight,
+        };
+
+        assert_approx_eq!(weight.x(), 1.0);
+    }
+}
@@ -0,0 +1,117 @@
+use std::f64::consts::PI;
+
+use crate::vec3::Vec3;
+
+#[cfg(test)]
+use assert_approx_eq::assert_approx_eq;
+
+#[derive(Debug, Clone, Copy)]
+pub struct TrowbridgeReitz {
+    pub alpha: f64,
+}
+
+impl TrowbridgeReitz {
+    pub fn new(roughness: f64) -> TrowbridgeReitz {
+        TrowbridgeReitz { alpha: (roughness * roughness).max(1e-3) }
+    }
+
+    pub fn d(&self, wm: &Vec3) -> f64 {
+        let cos2 = wm.z() * wm.z();
+        let a2 = self.alpha * self.alpha;
+        let denom = cos2 * (a2 - 1.0) + 1.0;
+
+        a2 / (PI * denom * denom)
+    }
+
+    pub fn lambda(&self, w: &Vec3) -> f64 {
+        let cos2 = w.z() * w.z();
+        let tan2 = (1.0 - cos2).max(0.0) / cos2;
+
+        if tan2.is_infinite() { return 0.0; }
+
+        ((1.0 + self.alpha * self.alpha * tan2).sqrt() - 1.0) / 2.0
+    }
+
+    pub fn g1(&self, w: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    pub fn g(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    pub fn d_visible(&self, w: &Vec3, wm: &Vec3) -> f64 {
+        self.g1(w) / w.z().abs() * self.d(wm) * w.dot(wm).abs()
+    }
+
+    pub fn sample_wm(&self, w: &Vec3, u: (f64, f64)) -> Vec3 {
+        let mut wh = Vec3::new(self.alpha * w.x(), self.alpha * w.y(), w.z()).unit_vec();
+
+        if wh.z() < 0.0 { wh = -wh; }
+
+        let t1 = if wh.z() < 0.99999 {
+            Vec3::new(0.0, 0.0, 1.0).cross(&wh).unit_vec()
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = wh.cross(&t1);
+        let r = u.0.sqrt();
+        let phi = 2.0 * PI * u.1;
+        let px = r * phi.cos();
+        let h = (1.0 - px * px).sqrt();
+        let s = (1.0 + wh.z()) / 2.0;
+        let py = (1.0 - s) * h + s * r * phi.sin();
+        let pz = (1.0 - px * px - py * py).max(0.0).sqrt();
+        let nh = t1 * px + t2 * py + wh * pz;
+
+        Vec3::new(self.alpha * nh.x(), self.alpha * nh.y(), nh.z().max(1e-6)).unit_vec()
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Gtr1 {
+    pub alpha: f64,
+}
+
+impl Gtr1 {
+    pub fn new(alpha: f64) -> Gtr1 {
+        Gtr1 { alpha: alpha.max(1e-3) }
+    }
+
+    pub fn d(&self, wm: &Vec3) -> f64 {
+        let a2 = self.alpha * self.alpha;
+
+        if a2 >= 1.0 { return 1.0 / PI; }
+
+        (a2 - 1.0) / (PI * a2.ln() * (1.0 + (a2 - 1.0) * wm.z() * wm.z()))
+    }
+
+    pub fn sample_wm(&self, u: (f64, f64)) -> Vec3 {
+        let a2 = self.alpha * self.alpha;
+        let cos_theta = if a2 >= 1.0 {
+            (1.0 - u.0).sqrt()
+        } else {
+            ((1.0 - a2.powf(1.0 - u.0)) / (1.0 - a2)).max(0.0).sqrt()
+        };
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * u.1;
+
+        Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta)
+    }
+}
+
+#[test]
+fn test_trowbridge_reitz_normalized() {
+    let distrib = TrowbridgeReitz::new(0.5);
+    let n = 2000;
+    let mut integral = 0.0;
+
+    for i in 0..n {
+        let cos_theta = ((i as f64) + 0.5) / (n as f64);
+        let wm = Vec3::new((1.0 - cos_theta * cos_theta).sqrt(), 0.0, cos_theta);
+
+        integral += distrib.d(&wm) * cos_theta * 2.0 * PI / (n as f64);
+    }
+
+    assert_approx_eq!(integral, 1.0, 1e-3);
+}
@@ -1,5 +1,8 @@
+use serde::{ Serialize, Deserialize };
+
 use crate::vec3::Vec3;
 use crate::materials::Materials;
+use crate::sampler::PixelSampler;
 
 #[cfg(test)]
 use assert_approx_eq::assert_approx_eq;
@@ -20,16 +23,87 @@ impl Ray {
     }
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BackFaceMode {
+    #[default]
+    Shade,
+    Black,
+    Cull,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BackFace {
+    pub mode: BackFaceMode,
+    pub emit: bool,
+}
+
+impl Default for BackFace {
+    fn default() -> BackFace {
+        BackFace { mode: BackFaceMode::Shade, emit: true }
+    }
+}
+
+impl BackFace {
+    pub fn new(mode: BackFaceMode, emit: bool) -> BackFace {
+        BackFace { mode, emit }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RayKind {
+    Camera,
+    Shadow,
+    Diffuse,
+    Specular,
+    Transmission,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Visibility {
+    pub camera: bool,
+    pub shadow: bool,
+    pub diffuse: bool,
+    pub specular: bool,
+    pub transmission: bool,
+}
+
+impl Default for Visibility {
+    fn default() -> Visibility {
+        Visibility { camera: true, shadow: true, diffuse: true, specular: true, transmission: true }
+    }
+}
+
+impl Visibility {
+    pub fn visible(&self, kind: RayKind) -> bool {
+        match kind {
+            RayKind::Camera => self.camera,
+            RayKind::Shadow => self.shadow,
+            RayKind::Diffuse => self.diffuse,
+            RayKind::Specular => self.specular,
+            RayKind::Transmission => self.transmission,
+        }
+    }
+}
+
 pub struct HitRecord<'material> {
     pub pt: Vec3,
     pub norm: Vec3,
+    pub geo_norm: Vec3,
+    pub dpdu: Vec3,
+    pub dpdv: Vec3,
     pub t: f64,
+    pub u: f64,
+    pub v: f64,
     pub front_face: bool,
+    pub back_face: BackFace,
     pub mat: &'material Materials,
+    pub name: &'material str,
 }
 
 pub trait Hittable {
-    fn hit(&self, ray: &Ray, ray_tmin: f64, ray_tmax: f64) -> Option<HitRecord>;
+    fn hit(&self, ray: &Ray, ray_tmin: f64, ray_tmax: f64, sampler: &PixelSampler) -> Option<HitRecord<'_>>;
 }
 
 #[test]
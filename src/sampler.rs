@@ -0,0 +1,549 @@
+use std::sync::OnceLock;
+
+#[cfg(test)]
+use assert_approx_eq::assert_approx_eq;
+
+pub const PIXEL_DIMENSION: u32 = 0;
+pub const LENS_DIMENSION: u32 = 2;
+pub const TIME_DIMENSION: u32 = 4;
+pub const WAVELENGTH_DIMENSION: u32 = 5;
+pub const BOUNCE_DIMENSION: u32 = 6;
+pub const DIMENSIONS_PER_BOUNCE: u32 = 17;
+
+pub const LIGHT_SELECT_DIMENSION: u32 = 0;
+pub const LIGHT_DIMENSION: u32 = 1;
+pub const BSDF_DIMENSION: u32 = 3;
+pub const DIRECTIONAL_DIMENSION: u32 = 7;
+pub const SKY_DIMENSION: u32 = 9;
+pub const MEDIUM_DIMENSION: u32 = 12;
+pub const MATERIAL_DIMENSION: u32 = 16;
+
+const OVERFLOW_DIMENSION: u32 = 1 << 31;
+const PRIMES: [u64; 64] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53,
+    59, 61, 67, 71, 73, 79, 83, 89, 97, 101, 103, 107, 109, 113, 127, 131,
+    137, 139, 149, 151, 157, 163, 167, 173, 179, 181, 191, 193, 197, 199, 211, 223,
+    227, 229, 233, 239, 241, 251, 257, 263, 269, 271, 277, 281, 283, 293, 307, 311,
+];
+
+const BLUE_NOISE_SIZE: usize = 64;
+
+static BLUE_NOISE: OnceLock<Vec<f64>> = OnceLock::new();
+
+pub trait Sampler {
+    fn sample_1d(&self, key: &PixelKey, index: u32, dimension: u32) -> f64;
+
+    fn sample_2d(&self, key: &PixelKey, index: u32, dimension: u32) -> (f64, f64) {
+        (self.sample_1d(key, index, dimension), self.sample_1d(key, index, dimension + 1))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct IndependentSampler;
+
+impl Sampler for IndependentSampler {
+    fn sample_1d(&self, key: &PixelKey, index: u32, dimension: u32) -> f64 {
+        to_unit(hash(&[key.hash, index as u64, dimension as u64]))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct StratifiedSampler {
+    pub samples_per_pixel: u32,
+}
+
+impl StratifiedSampler {
+    pub fn new(samples_per_pixel: u32) -> StratifiedSampler {
+        StratifiedSampler { samples_per_pixel: samples_per_pixel.max(1) }
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn sample_1d(&self, key: &PixelKey, index: u32, dimension: u32) -> f64 {
+        let n = self.samples_per_pixel;
+        let seed = hash(&[key.hash, dimension as u64]);
+        let stratum = permutation_element(index % n, n, seed as u32);
+        let jitter = to_unit(hash(&[seed, index as u64]));
+
+        ((stratum as f64) + jitter) / (n as f64)
+    }
+
+    fn sample_2d(&self, key: &PixelKey, index: u32, dimension: u32) -> (f64, f64) {
+        let nx = (self.samples_per_pixel as f64).sqrt() as u32;
+        let ny = self.samples_per_pixel / nx;
+        let seed = hash(&[key.hash, dimension as u64]);
+        let stratum = permutation_element(index % (nx * ny), nx * ny, seed as u32);
+        let jitter = (to_unit(hash(&[seed, index as u64, 0])), to_unit(hash(&[seed, index as u64, 1])));
+
+        (((stratum % nx) as f64 + jitter.0) / (nx as f64), ((stratum / nx) as f64 + jitter.1) / (ny as f64))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct HaltonSampler;
+
+impl Sampler for HaltonSampler {
+    fn sample_1d(&self, key: &PixelKey, index: u32, dimension: u32) -> f64 {
+        let Some(base) = PRIMES.get(dimension as usize) else { return IndependentSampler.sample_1d(key, index, dimension); };
+
+        scrambled_radical_inverse(*base, index as u64, hash(&[key.hash, dimension as u64]))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SobolSampler;
+
+impl Sampler for SobolSampler {
+    fn sample_1d(&self, key: &PixelKey, index: u32, dimension: u32) -> f64 {
+        sobol_1d(index, hash(&[key.hash, dimension as u64]) as u32)
+    }
+
+    fn sample_2d(&self, key: &PixelKey, index: u32, dimension: u32) -> (f64, f64) {
+        sobol_2d(index, hash(&[key.hash, dimension as u64]) as u32)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BlueNoiseSampler;
+
+impl Sampler for BlueNoiseSampler {
+    fn sample_1d(&self, key: &PixelKey, index: u32, dimension: u32) -> f64 {
+        let x = sobol_1d(index, hash(&[key.seed, dimension as u64]) as u32);
+
+        (x + blue_noise(key.pixel, dimension)).fract()
+    }
+
+    fn sample_2d(&self, key: &PixelKey, index: u32, dimension: u32) -> (f64, f64) {
+        let (x, y) = sobol_2d(index, hash(&[key.seed, dimension as u64]) as u32);
+
+        ((x + blue_noise(key.pixel, dimension)).fract(), (y + blue_noise(key.pixel, dimension + 1)).fract())
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Samplers {
+    Independent(IndependentSampler),
+    Stratified(StratifiedSampler),
+    Halton(HaltonSampler),
+    Sobol(SobolSampler),
+    BlueNoise(BlueNoiseSampler),
+}
+
+impl Samplers {
+    pub fn with_samples_per_pixel(self, samples_per_pixel: u32) -> Samplers {
+        match self {
+            Samplers::Stratified(_) => { Samplers::Stratified(StratifiedSampler::new(samples_per_pixel)) }
+            _ => { self }
+        }
+    }
+}
+
+impl Default for Samplers {
+    fn default() -> Samplers {
+        Samplers::Independent(IndependentSampler)
+    }
+}
+
+impl Sampler for Samplers {
+    fn sample_1d(&self, key: &PixelKey, index: u32, dimension: u32) -> f64 {
+        match self {
+            Samplers::Independent(s) => { s.sample_1d(key, index, dimension) }
+            Samplers::Stratified(s) => { s.sample_1d(key, index, dimension) }
+            Samplers::Halton(s) => { s.sample_1d(key, index, dimension) }
+            Samplers::Sobol(s) => { s.sample_1d(key, index, dimension) }
+            Samplers::BlueNoise(s) => { s.sample_1d(key, index, dimension) }
+        }
+    }
+
+    fn sample_2d(&self, key: &PixelKey, index: u32, dimension: u32) -> (f64, f64) {
+        match self {
+            Samplers::Independent(s) => { s.sample_2d(key, index, dimension) }
+            Samplers::Stratified(s) => { s.sample_2d(key, index, dimension) }
+            Samplers::Halton(s) => { s.sample_2d(key, index, dimension) }
+            Samplers::Sobol(s) => { s.sample_2d(key, index, dimension) }
+            Samplers::BlueNoise(s) => { s.sample_2d(key, index, dimension) }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PixelKey {
+    pub pixel: (u32, u32),
+    pub seed: u64,
+    pub hash: u64,
+}
+
+#[derive(Debug, Clone)]
+pub struct PixelSampler {
+    sampler: Samplers,
+    key: PixelKey,
+    index: u32,
+    dimension: u32,
+    limit: u32,
+    bounce: u32,
+    overflow: u32,
+}
+
+impl PixelSampler {
+    pub fn new(sampler: Samplers, seed: u64, pixel: (u32, u32), index: u32) -> PixelSampler {
+        PixelSampler {
+            sampler,
+            key: PixelKey { pixel, seed, hash: hash(&[seed, pixel.0 as u64, pixel.1 as u64]) },
+            index,
+            dimension: 0,
+            limit: u32::MAX,
+            bounce: 0,
+            overflow: 0,
+        }
+    }
+
+    pub fn start_dimension(&mut self, dimension: u32) {
+        self.dimension = dimension;
+        self.limit = BOUNCE_DIMENSION;
+    }
+
+    pub fn start_bounce(&mut self) {
+        self.dimension = BOUNCE_DIMENSION + self.bounce * DIMENSIONS_PER_BOUNCE;
+        self.limit = self.dimension + DIMENSIONS_PER_BOUNCE;
+        self.bounce += 1;
+    }
+
+    pub fn start_bounce_dimension(&mut self, offset: u32, count: u32) {
+        self.dimension = BOUNCE_DIMENSION + self.bounce.saturating_sub(1) * DIMENSIONS_PER_BOUNCE + offset;
+        self.limit = self.dimension + count;
+    }
+
+    fn next_dimension(&mut self, count: u32) -> (Samplers, u32) {
+        if self.dimension.saturating_add(count) > self.limit {
+            self.overflow += count;
+
+            return (Samplers::default(), OVERFLOW_DIMENSION + self.overflow - count);
+        }
+
+        self.dimension += count;
+
+        (self.sampler, self.dimension - count)
+    }
+
+    pub fn get_1d(&mut self) -> f64 {
+        let (sampler, dimension) = self.next_dimension(1);
+
+        sampler.sample_1d(&self.key, self.index, dimension)
+    }
+
+    pub fn get_2d(&mut self) -> (f64, f64) {
+        let (sampler, dimension) = self.next_dimension(2);
+
+        sampler.sample_2d(&self.key, self.index, dimension)
+    }
+
+    pub fn hash_1d(&self, values: &[f64]) -> f64 {
+        let h = values.iter().fold(hash(&[self.key.hash, self.index as u64]), |h, v| hash(&[h, v.to_bits()]));
+
+        to_unit(h)
+    }
+}
+
+fn hash(values: &[u64]) -> u64 {
+    let mut h: u64 = 0x9e3779b97f4a7c15;
+
+    for v in values {
+        h ^= *v;
+        h = (h ^ (h >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        h = (h ^ (h >> 27)).wrapping_mul(0x94d049bb133111eb);
+        h ^= h >> 31;
+    }
+
+    h
+}
+
+fn hash_u32(x: u32) -> u32 {
+    hash(&[x as u64]) as u32
+}
+
+fn to_unit(h: u64) -> f64 {
+    ((h >> 11) as f64) / ((1u64 << 53) as f64)
+}
+
+fn scrambled_radical_inverse(base: u64, mut i: u64, seed: u64) -> f64 {
+    let inv_base = 1.0 / (base as f64);
+    let digits = (53.0 * 2.0_f64.ln() / (base as f64).ln()).ceil() as u64;
+    let mut inv = 1.0;
+    let mut value = 0.0;
+
+    for position in 0..digits {
+        let digit = permutation_element((i % base) as u32, base as u32, hash(&[seed, position]) as u32);
+
+        inv *= inv_base;
+        value += (digit as f64) * inv;
+        i /= base;
+    }
+
+    value.min(1.0 - f64::EPSILON)
+}
+
+fn sobol_1d(index: u32, seed: u32) -> f64 {
+    let index = nested_uniform_scramble(index, seed);
+
+    (nested_uniform_scramble(index.reverse_bits(), hash_u32(seed)) as f64) / 4294967296.0
+}
+
+fn sobol_2d(index: u32, seed: u32) -> (f64, f64) {
+    let index = nested_uniform_scramble(index, seed);
+    let seed = hash_u32(seed);
+    let x = nested_uniform_scramble(index.reverse_bits(), seed);
+    let y = nested_uniform_scramble(sobol_second_dimension(index), hash_u32(seed));
+
+    ((x as f64) / 4294967296.0, (y as f64) / 4294967296.0)
+}
+
+fn blue_noise(pixel: (u32, u32), dimension: u32) -> f64 {
+    let n = BLUE_NOISE_SIZE;
+    let mask = BLUE_NOISE.get_or_init(|| void_and_cluster(n));
+    let offset_x = ((dimension as f64) * 0.7548776662466927).fract() * (n as f64);
+    let offset_y = ((dimension as f64) * 0.5698402909980532).fract() * (n as f64);
+    let x = (pixel.0 as usize + offset_x as usize) % n;
+    let y = (pixel.1 as usize + offset_y as usize) % n;
+
+    mask[y * n + x]
+}
+
+fn void_and_cluster(n: usize) -> Vec<f64> {
+    let size = n * n;
+    let kernel: Vec<f64> = (0..size).map(|i| {
+        let dx = (i % n).min(n - i % n) as f64;
+        let dy = (i / n).min(n - i / n) as f64;
+
+        (-(dx * dx + dy * dy) / (2.0 * 1.5 * 1.5)).exp()
+    }).collect();
+    let update = |energy: &mut [f64], i: usize, sign: f64| {
+        let (px, py) = (i % n, i / n);
+
+        for y in 0..n {
+            for x in 0..n {
+                energy[((py + y) % n) * n + (px + x) % n] += sign * kernel[y * n + x];
+            }
+        }
+    };
+    let extreme = |pattern: &[bool], energy: &[f64], set: bool, max: bool| {
+        (0..size).filter(|i| pattern[*i] == set).max_by(|a, b| {
+            let order = energy[*a].total_cmp(&energy[*b]);
+
+            if max { order } else { order.reverse() }
+        }).unwrap()
+    };
+    let mut pattern = vec![false; size];
+    let mut energy = vec![0.0; size];
+
+    for k in 0..size / 10 {
+        let i = (hash(&[k as u64]) % size as u64) as usize;
+
+        if pattern[i] { continue; }
+
+        pattern[i] = true;
+        update(&mut energy, i, 1.0);
+    }
+
+    for _ in 0..size {
+        let cluster = extreme(&pattern, &energy, true, true);
+
+        pattern[cluster] = false;
+        update(&mut energy, cluster, -1.0);
+
+        let void = extreme(&pattern, &energy, false, false);
+
+        pattern[void] = true;
+        update(&mut energy, void, 1.0);
+
+        if void == cluster { break; }
+    }
+
+    let ones = pattern.iter().filter(|p| **p).count();
+    let mut rank = vec![0; size];
+    let (mut prototype, mut prototype_energy) = (pattern.clone(), energy.clone());
+
+    for r in (0..ones).rev() {
+        let cluster = extreme(&prototype, &prototype_energy, true, true);
+
+        prototype[cluster] = false;
+        update(&mut prototype_energy, cluster, -1.0);
+        rank[cluster] = r;
+    }
+
+    for r in ones..size {
+        let void = extreme(&pattern, &energy, false, false);
+
+        pattern[void] = true;
+        update(&mut energy, void, 1.0);
+        rank[void] = r;
+    }
+
+    rank.iter().map(|r| ((*r as f64) + 0.5) / (size as f64)).collect()
+}
+
+fn sobol_second_dimension(mut index: u32) -> u32 {
+    let mut v: u32 = 1 << 31;
+    let mut result = 0;
+
+    while index != 0 {
+        if index & 1 != 0 { result ^= v; }
+
+        index >>= 1;
+        v ^= v >> 1;
+    }
+
+    result
+}
+
+fn laine_karras_permutation(mut x: u32, seed: u32) -> u32 {
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+
+    x
+}
+
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    laine_karras_permutation(x.reverse_bits(), seed).reverse_bits()
+}
+
+fn permutation_element(mut i: u32, l: u32, p: u32) -> u32 {
+    let mut w = l - 1;
+
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+
+    loop {
+        i ^= p;
+        i = i.wrapping_mul(0xe170893d);
+        i ^= p >> 16;
+        i ^= (i & w) >> 4;
+        i ^= p >> 8;
+        i = i.wrapping_mul(0x0929eb3f);
+        i ^= p >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | p >> 27);
+        i = i.wrapping_mul(0x6935fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dcb303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e501cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860a3df);
+        i &= w;
+        i ^= i >> 5;
+
+        if i < l { break; }
+    }
+
+    (i.wrapping_add(p)) % l
+}
+
+#[test]
+fn test_low_discrepancy_error() {
+    let error = |sampler: Samplers| {
+        let mut mse = 0.0;
+
+        for pixel in 0..256 {
+            let mut estimate = 0.0;
+
+            for index in 0..16 {
+                let mut pixel_sampler = PixelSampler::new(sampler, 1, (pixel, 0), index);
+
+                pixel_sampler.start_dimension(PIXEL_DIMENSION);
+
+                let (x, y) = pixel_sampler.get_2d();
+                let inside = if x * x + y * y < 1.0 { 1.0 } else { 0.0 };
+
+                estimate += inside / 16.0;
+            }
+
+            mse += (estimate - std::f64::consts::PI / 4.0).powi(2) / 256.0;
+        }
+
+        mse
+    };
+    let independent = error(Samplers::Independent(IndependentSampler));
+
+
+    assert!(error(Samplers::Stratified(StratifiedSampler::new(16))) < 0.5 * independent);
+    assert!(error(Samplers::Halton(HaltonSampler)) < 0.5 * independent);
+    assert!(error(Samplers::Sobol(SobolSampler)) < 0.5 * independent);
+    assert!(error(Samplers::BlueNoise(BlueNoiseSampler)) < 0.5 * independent);
+}
+
+#[test]
+fn test_stratified_samples_per_pixel() {
+    let sampler = Samplers::Stratified(StratifiedSampler::new(4)).with_samples_per_pixel(16);
+    let mut strata = [false; 16];
+
+    for index in 0..16 {
+        let mut pixel_sampler = PixelSampler::new(sampler, 1, (0, 0), index);
+
+        pixel_sampler.start_dimension(TIME_DIMENSION);
+
+        let stratum = (pixel_sampler.get_1d() * 16.0) as usize;
+
+        assert!(!strata[stratum]);
+
+        strata[stratum] = true;
+    }
+
+    assert!(matches!(Samplers::Sobol(SobolSampler).with_samples_per_pixel(16), Samplers::Sobol(_)));
+}
+
+#[test]
+fn test_bounce_dimensions() {
+    let draw = |bsdf_draws: usize| {
+        let mut pixel_sampler = PixelSampler::new(Samplers::Sobol(SobolSampler), 1, (3, 4), 5);
+
+        pixel_sampler.start_bounce();
+        pixel_sampler.start_bounce_dimension(BSDF_DIMENSION, 4);
+
+        for _ in 0..bsdf_draws {
+            pixel_sampler.get_1d();
+        }
+
+        pixel_sampler.start_bounce_dimension(LIGHT_SELECT_DIMENSION, 1);
+
+        let select = pixel_sampler.get_1d();
+
+        pixel_sampler.start_bounce_dimension(SKY_DIMENSION, 3);
+
+        (select, pixel_sampler.get_2d())
+    };
+    let reference = draw(0);
+
+    for bsdf_draws in 1..8 {
+        assert_eq!(draw(bsdf_draws), reference);
+    }
+}
+
+#[test]
+fn test_blue_noise_mask() {
+    let n = BLUE_NOISE_SIZE;
+    let mask: Vec<f64> = (0..n * n).map(|i| blue_noise(((i % n) as u32, (i / n) as u32), 0)).collect();
+    let mut sorted = mask.clone();
+    let mut variance = 0.0;
+
+    sorted.sort_by(|a, b| a.total_cmp(b));
+
+    for (i, value) in sorted.iter().enumerate() {
+        assert_approx_eq!(*value, ((i as f64) + 0.5) / ((n * n) as f64));
+    }
+
+    for y in 0..n {
+        for x in 0..n {
+            let blurred = (0..16).map(|k| mask[((y + k / 4) % n) * n + (x + k % 4) % n]).sum::<f64>() / 16.0;
+
+            variance += (blurred - 0.5).powi(2) / ((n * n) as f64);
+        }
+    }
+
+    assert!(variance < 0.3 * (1.0 / 12.0 / 16.0));
+}
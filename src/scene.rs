@@ -0,0 +1,85 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::io::BufWriter;
+use serde::{ Serialize, Deserialize };
+
+use crate::sphere::Sphere;
+use crate::light::Lights;
+use crate::sky::Sky;
+use crate::light_tree::LightTree;
+
+#[cfg(test)]
+use crate::materials::Materials;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Scene {
+    pub objects: Vec<Sphere>,
+    #[serde(default)]
+    pub lights: Vec<Lights>,
+    #[serde(default)]
+    pub sky: Option<Sky>,
+}
+
+impl Scene {
+    pub fn new(objects: Vec<Sphere>) -> Scene {
+        Scene { objects, lights: vec![], sky: None }
+    }
+
+    pub fn with_lights(self, lights: Vec<Lights>) -> Scene {
+        Scene { lights, ..self }
+    }
+
+    pub fn with_sky(self, sky: Sky) -> Scene {
+        Scene { sky: Some(sky), ..self }
+    }
+
+    pub fn light_tree(&self) -> LightTree {
+        LightTree::new(&self.objects, &self.lights)
+    }
+
+    pub fn load(filename: &str) -> Result<Scene, std::io::Error> {
+        let file = File::open(filename)?;
+        let scene = serde_json::from_reader(BufReader::new(file))?;
+
+        Ok(scene)
+    }
+
+    pub fn save(&self, filename: &str) -> Result<(), std::io::Error> {
+        let file = File::create(filename)?;
+
+        serde_json::to_writer_pretty(BufWriter::new(file), self)?;
+
+        Ok(())
+    }
+}
+
+#[test]
+fn test_scene_from_json() {
+    let json = r#"{
+        "objects": [
+            {
+                "center": [0.0, 1.0, 0.0],
+                "radius": 1.0,
+                "mat": { "type": "Principled", "baseColor": [0.9, 0.1, 0.1], "metallic": 1.0, "clearcoatGloss": 0.5 }
+            },
+            {
+                "center": [0.0, -1000.0, 0.0],
+                "radius": 1000.0,
+                "mat": { "type": "Lambertian", "albedo": [0.5, 0.5, 0.5] }
+            }
+        ]
+    }"#;
+    let scene: Scene = serde_json::from_str(json).unwrap();
+
+    assert_eq!(scene.objects.len(), 2);
+
+    match &scene.objects[0].mat {
+        Materials::Principled(p) => {
+            assert_eq!(p.base_color.x(), 0.9);
+            assert_eq!(p.metallic, 1.0);
+            assert_eq!(p.clearcoat_gloss, 0.5);
+            assert_eq!(p.ior, 1.5);
+        }
+        _ => panic!("expected a principled material"),
+    }
+}
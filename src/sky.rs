@@ -0,0 +1,283 @@
+use std::f64::consts::PI;
+use serde::{ Serialize, Deserialize };
+
+use crate::vec3::Vec3;
+use crate::utils::onb;
+use crate::spectrum::xyz_to_srgb;
+use crate::materials::luminance;
+use crate::distribution::Distribution2D;
+use crate::light::Light;
+use crate::light::LightSample;
+use crate::light::LightLink;
+use crate::sampler::PixelSampler;
+
+#[cfg(test)]
+use assert_approx_eq::assert_approx_eq;
+#[cfg(test)]
+use crate::sampler::Samplers;
+
+const SKY_SCALE: f64 = 0.05;
+const SUN_ILLUMINANCE: f64 = 128.0;
+const SKY_RES_THETA: usize = 64;
+const SKY_RES_PHI: usize = 128;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct SkyParams {
+    pub sun_elevation: f64,
+    pub sun_azimuth: f64,
+    pub turbidity: f64,
+    pub ground_albedo: Vec3,
+    pub sun_size: f64,
+    pub intensity: f64,
+    pub link: LightLink,
+}
+
+impl Default for SkyParams {
+    fn default() -> SkyParams {
+        SkyParams {
+            sun_elevation: 45.0,
+            sun_azimuth: 0.0,
+            turbidity: 3.0,
+            ground_albedo: Vec3::new(0.3, 0.3, 0.3),
+            sun_size: 0.53,
+            intensity: 1.0,
+            link: LightLink::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "SkyParams", into = "SkyParams")]
+pub struct Sky {
+    pub params: SkyParams,
+    sun_dir: Vec3,
+    sun_cos_max: f64,
+    sun_radiance: Vec3,
+    sun_prob: f64,
+    zenith: [f64; 3],
+    perez: [[f64; 5]; 3],
+    ground: Vec3,
+    distribution: Distribution2D,
+}
+
+impl From<SkyParams> for Sky {
+    fn from(params: SkyParams) -> Sky {
+        Sky::new(params)
+    }
+}
+
+impl From<Sky> for SkyParams {
+    fn from(sky: Sky) -> SkyParams {
+        sky.params
+    }
+}
+
+impl Sky {
+    pub fn new(params: SkyParams) -> Sky {
+        let t = params.turbidity.max(1.0);
+        let elevation = params.sun_elevation.to_radians();
+        let azimuth = params.sun_azimuth.to_radians();
+        let sun_dir = Vec3::new(elevation.cos() * azimuth.cos(), elevation.sin(), elevation.cos() * azimuth.sin());
+        let theta_s = (PI / 2.0 - elevation).clamp(0.0, PI / 2.0);
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+        let zenith_y = ((4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192).max(0.0);
+        let zenith_x = zenith_chromaticity(t, theta_s, [
+            [0.00166, -0.00375, 0.00209, 0.0],
+            [-0.02903, 0.06377, -0.03202, 0.00394],
+            [0.11693, -0.21196, 0.06052, 0.25886],
+        ]);
+        let zenith_yc = zenith_chromaticity(t, theta_s, [
+            [0.00275, -0.00610, 0.00317, 0.0],
+            [-0.04214, 0.08970, -0.04153, 0.00516],
+            [0.15346, -0.26756, 0.06670, 0.26688],
+        ]);
+        let perez = [
+            [0.1787 * t - 1.4630, -0.3554 * t + 0.4275, -0.0227 * t + 5.3251, 0.1206 * t - 2.5771, -0.0670 * t + 0.3703],
+            [-0.0193 * t - 0.2592, -0.0665 * t + 0.0008, -0.0004 * t + 0.2125, -0.0641 * t - 0.8989, -0.0033 * t + 0.0452],
+            [-0.0167 * t - 0.2608, -0.0950 * t + 0.0092, -0.0079 * t + 0.2102, -0.0441 * t - 1.6537, -0.0109 * t + 0.0529],
+        ];
+        let zenith = [zenith_y, zenith_x, zenith_yc];
+        let zenith = [0, 1, 2].map(|i| zenith[i] / perez_f(&perez[i], 0.0, theta_s));
+        let sun_cos_max = (params.sun_size.to_radians() / 2.0).cos();
+        let sun_radiance = if elevation > 0.0 {
+            sun_transmittance(t, theta_s) * (SUN_ILLUMINANCE * SKY_SCALE * params.intensity / (2.0 * PI * (1.0 - sun_cos_max)))
+        } else {
+            Vec3::new(0.0, 0.0, 0.0)
+        };
+
+        let mut sky = Sky {
+            params,
+            sun_dir,
+            sun_cos_max,
+            sun_radiance,
+            sun_prob: 0.0,
+            zenith,
+            perez,
+            ground: Vec3::new(0.0, 0.0, 0.0),
+            distribution: Distribution2D::new(&[1.0], 1, 1),
+        };
+
+        let mut sky_irradiance = Vec3::new(0.0, 0.0, 0.0);
+        let mut func = vec![0.0; SKY_RES_THETA * SKY_RES_PHI];
+
+        for v in 0..SKY_RES_THETA / 2 {
+            for u in 0..SKY_RES_PHI {
+                let (dir, sin_theta) = direction(((u as f64) + 0.5) / (SKY_RES_PHI as f64), ((v as f64) + 0.5) / (SKY_RES_THETA as f64));
+                let radiance = sky.sky_radiance(&dir);
+                let d_omega = 2.0 * PI * PI * sin_theta / ((SKY_RES_PHI * SKY_RES_THETA) as f64);
+
+                sky_irradiance = sky_irradiance + radiance * (dir.y() * d_omega);
+                func[v * SKY_RES_PHI + u] = luminance(&radiance) * sin_theta;
+            }
+        }
+
+        let sun_irradiance = sky.sun_radiance * (2.0 * PI * (1.0 - sun_cos_max) * sun_dir.y().max(0.0));
+
+        sky.ground = sky.params.ground_albedo * (sky_irradiance + sun_irradiance) / PI;
+
+        for v in SKY_RES_THETA / 2..SKY_RES_THETA {
+            for u in 0..SKY_RES_PHI {
+                let sin_theta = (PI * ((v as f64) + 0.5) / (SKY_RES_THETA as f64)).sin();
+
+                func[v * SKY_RES_PHI + u] = luminance(&sky.ground) * sin_theta;
+            }
+        }
+
+        sky.distribution = Distribution2D::new(&func, SKY_RES_PHI, SKY_RES_THETA);
+
+        let sun_power = luminance(&sun_irradiance);
+        let sky_power = luminance(&sky_irradiance);
+
+        if sun_power > 0.0 {
+            sky.sun_prob = (sun_power / (sun_power + sky_power)).clamp(0.1, 0.9);
+        }
+
+        sky
+    }
+
+    fn sky_radiance(&self, dir: &Vec3) -> Vec3 {
+        if dir.y() < 0.0 { return self.ground; }
+
+        let theta = dir.y().clamp(0.0, 1.0).acos();
+        let gamma = dir.dot(&self.sun_dir).clamp(-1.0, 1.0).acos();
+        let big_y = self.zenith[0] * perez_f(&self.perez[0], theta, gamma);
+        let x = self.zenith[1] * perez_f(&self.perez[1], theta, gamma);
+        let y = self.zenith[2] * perez_f(&self.perez[2], theta, gamma);
+
+        if y <= 0.0 { return Vec3::new(0.0, 0.0, 0.0); }
+
+        let xyz = Vec3::new(x / y * big_y, big_y, (1.0 - x - y) / y * big_y);
+        let rgb = xyz_to_srgb(&xyz);
+
+        Vec3::new(rgb.x().max(0.0), rgb.y().max(0.0), rgb.z().max(0.0)) * (SKY_SCALE * self.params.intensity)
+    }
+
+    pub fn radiance(&self, dir: &Vec3) -> Vec3 {
+        let dir = dir.unit_vec();
+        let sky = self.sky_radiance(&dir);
+
+        if dir.dot(&self.sun_dir) >= self.sun_cos_max { return sky + self.sun_radiance; }
+
+        sky
+    }
+
+    pub fn pdf(&self, dir: &Vec3) -> f64 {
+        let dir = dir.unit_vec();
+        let theta = dir.y().clamp(-1.0, 1.0).acos();
+        let sin_theta = theta.sin();
+        let phi = dir.z().atan2(dir.x()).rem_euclid(2.0 * PI);
+        let sky_pdf = if sin_theta > 0.0 {
+            self.distribution.pdf((phi / (2.0 * PI), theta / PI)) / (2.0 * PI * PI * sin_theta)
+        } else {
+            0.0
+        };
+        let sun_pdf = if dir.dot(&self.sun_dir) >= self.sun_cos_max {
+            1.0 / (2.0 * PI * (1.0 - self.sun_cos_max))
+        } else {
+            0.0
+        };
+
+        self.sun_prob * sun_pdf + (1.0 - self.sun_prob) * sky_pdf
+    }
+}
+
+impl Light for Sky {
+    fn sample_li(&self, _pt: &Vec3, sampler: &mut PixelSampler) -> Option<LightSample> {
+        let wi = if sampler.get_1d() < self.sun_prob {
+            let cos_theta = 1.0 - sampler.get_1d() * (1.0 - self.sun_cos_max);
+            let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+            let phi = 2.0 * PI * sampler.get_1d();
+            let (t, b) = onb(&self.sun_dir);
+
+            t * (sin_theta * phi.cos()) + b * (sin_theta * phi.sin()) + self.sun_dir * cos_theta
+        } else {
+            let ((u, v), _) = self.distribution.sample(sampler.get_2d());
+
+            direction(u, v).0
+        };
+        let pdf = self.pdf(&wi);
+
+        if pdf <= 0.0 { return None; }
+
+        Some(LightSample { wi, dist: f64::INFINITY, radiance: self.radiance(&wi), pdf })
+    }
+}
+
+fn direction(u: f64, v: f64) -> (Vec3, f64) {
+    let theta = v * PI;
+    let phi = u * 2.0 * PI;
+    let sin_theta = theta.sin();
+
+    (Vec3::new(sin_theta * phi.cos(), theta.cos(), sin_theta * phi.sin()), sin_theta)
+}
+
+fn perez_f(coeffs: &[f64; 5], theta: f64, gamma: f64) -> f64 {
+    let [a, b, c, d, e] = *coeffs;
+    let cos_theta = theta.cos().max(0.01);
+    let cos_gamma = gamma.cos();
+
+    (1.0 + a * (b / cos_theta).exp()) * (1.0 + c * (d * gamma).exp() + e * cos_gamma * cos_gamma)
+}
+
+fn zenith_chromaticity(t: f64, theta_s: f64, m: [[f64; 4]; 3]) -> f64 {
+    let poly = |row: [f64; 4]| ((row[0] * theta_s + row[1]) * theta_s + row[2]) * theta_s + row[3];
+
+    t * t * poly(m[0]) + t * poly(m[1]) + poly(m[2])
+}
+
+fn sun_transmittance(t: f64, theta_s: f64) -> Vec3 {
+    let air_mass = 1.0 / (theta_s.cos() + 0.15 * (93.885 - theta_s.to_degrees()).powf(-1.253));
+    let beta = 0.04608 * t - 0.04586;
+    let tau = |lambda: f64| {
+        let rayleigh = (-0.008735 * lambda.powf(-4.08) * air_mass).exp();
+        let aerosol = (-beta * lambda.powf(-1.3) * air_mass).exp();
+
+        rayleigh * aerosol
+    };
+
+    Vec3::new(tau(0.68), tau(0.55), tau(0.44))
+}
+
+#[test]
+fn test_sky_sampling() {
+    let sky = Sky::new(SkyParams { sun_elevation: 30.0, ..SkyParams::default() });
+    let n = 20000;
+    let mut estimate = Vec3::new(0.0, 0.0, 0.0);
+    let mut reference = Vec3::new(0.0, 0.0, 0.0);
+    let mut sampler = PixelSampler::new(Samplers::default(), 0, (0, 0), 0);
+
+    for _ in 0..n {
+        let sample = sky.sample_li(&Vec3::new(0.0, 0.0, 0.0), &mut sampler).unwrap();
+
+        assert_approx_eq!(sky.pdf(&sample.wi), sample.pdf, 1e-6);
+
+        estimate = estimate + sample.radiance / sample.pdf / (n as f64);
+        reference = reference + sky.sky_radiance(&Vec3::random_unit_vec(&mut sampler)) * (4.0 * PI / (n as f64));
+    }
+
+    let sun_power = sky.sun_radiance * (2.0 * PI * (1.0 - sky.sun_cos_max));
+
+    assert!(sky.radiance(&Vec3::new(0.0, 1.0, 0.0)).z() > sky.radiance(&Vec3::new(0.0, 1.0, 0.0)).x());
+    assert_approx_eq!(estimate.y(), reference.y() + sun_power.y(), 0.05 * estimate.y());
+}
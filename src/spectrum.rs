@@ -0,0 +1,465 @@
+use std::ops::{ Add, Mul, Div };
+use std::sync::OnceLock;
+
+use crate::vec3::Vec3;
+
+#[cfg(test)]
+use assert_approx_eq::assert_approx_eq;
+
+pub const LAMBDA_MIN: f64 = 360.0;
+pub const LAMBDA_MAX: f64 = 830.0;
+pub const N_SPECTRUM_SAMPLES: usize = 4;
+
+const TABLE_RES: usize = 16;
+const FIT_STEP: f64 = 5.0;
+const FIT_SAMPLES: usize = ((LAMBDA_MAX - LAMBDA_MIN) / FIT_STEP) as usize + 1;
+
+const XYZ_TO_SRGB: [[f64; 3]; 3] = [
+    [3.2404542, -1.5371385, -0.4985314],
+    [-0.9692660, 1.8760108, 0.0415560],
+    [0.0556434, -0.2040259, 1.0572252],
+];
+
+pub trait Wavelengths {
+    type Radiance: Copy
+        + Add<Output = Self::Radiance>
+        + Mul<Output = Self::Radiance>
+        + Mul<f64, Output = Self::Radiance>;
+
+    fn upsample(&self, rgb: Vec3) -> Self::Radiance;
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Rgb;
+
+impl Wavelengths for Rgb {
+    type Radiance = Vec3;
+
+    fn upsample(&self, rgb: Vec3) -> Vec3 {
+        rgb
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SampledSpectrum {
+    values: [f64; N_SPECTRUM_SAMPLES],
+}
+
+impl SampledSpectrum {
+    pub fn new(values: [f64; N_SPECTRUM_SAMPLES]) -> SampledSpectrum {
+        SampledSpectrum { values }
+    }
+
+    pub fn constant(c: f64) -> SampledSpectrum {
+        SampledSpectrum { values: [c; N_SPECTRUM_SAMPLES] }
+    }
+
+    pub fn values(&self) -> [f64; N_SPECTRUM_SAMPLES] {
+        self.values
+    }
+
+    pub fn average(&self) -> f64 {
+        self.values.iter().sum::<f64>() / (N_SPECTRUM_SAMPLES as f64)
+    }
+
+    pub fn max(&self) -> f64 {
+        self.values.iter().cloned().fold(f64::MIN, f64::max)
+    }
+}
+
+impl Add for SampledSpectrum {
+    type Output = SampledSpectrum;
+
+    fn add(self, s: SampledSpectrum) -> SampledSpectrum {
+        let mut values = self.values;
+
+        for (v, o) in values.iter_mut().zip(s.values.iter()) { *v += o; }
+
+        SampledSpectrum { values }
+    }
+}
+
+impl Mul for SampledSpectrum {
+    type Output = SampledSpectrum;
+
+    fn mul(self, s: SampledSpectrum) -> SampledSpectrum {
+        let mut values = self.values;
+
+        for (v, o) in values.iter_mut().zip(s.values.iter()) { *v *= o; }
+
+        SampledSpectrum { values }
+    }
+}
+
+impl Mul<f64> for SampledSpectrum {
+    type Output = SampledSpectrum;
+
+    fn mul(self, scale: f64) -> SampledSpectrum {
+        let mut values = self.values;
+
+        for v in values.iter_mut() { *v *= scale; }
+
+        SampledSpectrum { values }
+    }
+}
+
+impl Div<f64> for SampledSpectrum {
+    type Output = SampledSpectrum;
+
+    fn div(self, scale: f64) -> SampledSpectrum {
+        self * (1.0 / scale)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SampledWavelengths {
+    pub lambda: [f64; N_SPECTRUM_SAMPLES],
+    pub pdf: [f64; N_SPECTRUM_SAMPLES],
+}
+
+impl SampledWavelengths {
+    pub fn sample_visible(u: f64) -> SampledWavelengths {
+        let mut lambda = [0.0; N_SPECTRUM_SAMPLES];
+        let mut pdf = [0.0; N_SPECTRUM_SAMPLES];
+
+        for i in 0..N_SPECTRUM_SAMPLES {
+            let up = (u + (i as f64) / (N_SPECTRUM_SAMPLES as f64)).fract();
+
+            lambda[i] = sample_visible_wavelength(up);
+            pdf[i] = visible_wavelength_pdf(lambda[i]);
+        }
+
+        SampledWavelengths { lambda, pdf }
+    }
+
+    pub fn to_xyz(&self, s: &SampledSpectrum) -> Vec3 {
+        let mut xyz = Vec3::new(0.0, 0.0, 0.0);
+
+        for i in 0..N_SPECTRUM_SAMPLES {
+            if self.pdf[i] == 0.0 { continue; }
+
+            xyz = xyz + cie_xyz(self.lambda[i]) * (s.values[i] / self.pdf[i]);
+        }
+
+        xyz / ((N_SPECTRUM_SAMPLES as f64) * sigmoid_table().cie_y_integral)
+    }
+}
+
+impl Wavelengths for SampledWavelengths {
+    type Radiance = SampledSpectrum;
+
+    fn upsample(&self, rgb: Vec3) -> SampledSpectrum {
+        let m = rgb.x().max(rgb.y()).max(rgb.z());
+
+        if m <= 0.0 { return SampledSpectrum::constant(0.0); }
+
+        let (scale, rsp) = if m <= 1.0 {
+            (1.0, sigmoid_table().coefficients(rgb))
+        } else {
+            (2.0 * m, sigmoid_table().coefficients(rgb / (2.0 * m)))
+        };
+        let mut values = [0.0; N_SPECTRUM_SAMPLES];
+
+        for (v, lambda) in values.iter_mut().zip(self.lambda.iter()) {
+            *v = rsp.eval(*lambda) * scale;
+        }
+
+        SampledSpectrum { values }
+    }
+}
+
+pub fn sample_visible_wavelength(u: f64) -> f64 {
+    538.0 - 138.888889 * (0.85691062 - 1.82750197 * u).atanh()
+}
+
+pub fn visible_wavelength_pdf(lambda: f64) -> f64 {
+    if !(LAMBDA_MIN..=LAMBDA_MAX).contains(&lambda) { return 0.0; }
+
+    0.0039398042 / (0.0072 * (lambda - 538.0)).cosh().powi(2)
+}
+
+fn lobe(lambda: f64, mu: f64, sigma1: f64, sigma2: f64) -> f64 {
+    let t = (lambda - mu) / if lambda < mu { sigma1 } else { sigma2 };
+
+    (-0.5 * t * t).exp()
+}
+
+pub fn cie_xyz(lambda: f64) -> Vec3 {
+    Vec3::new(
+        1.056 * lobe(lambda, 599.8, 37.9, 31.0) + 0.362 * lobe(lambda, 442.0, 16.0, 26.7) - 0.065 * lobe(lambda, 501.1, 20.4, 26.2),
+        0.821 * lobe(lambda, 568.8, 46.9, 40.5) + 0.286 * lobe(lambda, 530.9, 16.3, 31.1),
+        1.217 * lobe(lambda, 437.0, 11.8, 36.0) + 0.681 * lobe(lambda, 459.0, 26.0, 13.8),
+    )
+}
+
+pub fn xyz_to_srgb(xyz: &Vec3) -> Vec3 {
+    let white = sigmoid_table().white;
+    let rgb = xyz_to_linear(xyz);
+
+    Vec3::new(rgb.x() / white.x(), rgb.y() / white.y(), rgb.z() / white.z())
+}
+
+fn xyz_to_linear(xyz: &Vec3) -> Vec3 {
+    let m = XYZ_TO_SRGB;
+
+    Vec3::new(
+        m[0][0] * xyz.x() + m[0][1] * xyz.y() + m[0][2] * xyz.z(),
+        m[1][0] * xyz.x() + m[1][1] * xyz.y() + m[1][2] * xyz.z(),
+        m[2][0] * xyz.x() + m[2][1] * xyz.y() + m[2][2] * xyz.z(),
+    )
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RgbSigmoid {
+    c: [f64; 3],
+}
+
+impl RgbSigmoid {
+    pub fn eval(&self, lambda: f64) -> f64 {
+        let x = (lambda - LAMBDA_MIN) / (LAMBDA_MAX - LAMBDA_MIN);
+        let p = (self.c[0] * x + self.c[1]) * x + self.c[2];
+
+        if p.is_infinite() { return if p > 0.0 { 1.0 } else { 0.0 }; }
+
+        0.5 + p / (2.0 * (1.0 + p * p).sqrt())
+    }
+}
+
+struct SigmoidTable {
+    z_nodes: Vec<f64>,
+    coeffs: Vec<[f64; 3]>,
+    cie_y_integral: f64,
+    white: Vec3,
+}
+
+fn sigmoid_table() -> &'static SigmoidTable {
+    static TABLE: OnceLock<SigmoidTable> = OnceLock::new();
+
+    TABLE.get_or_init(SigmoidTable::build)
+}
+
+impl SigmoidTable {
+    fn build() -> SigmoidTable {
+        let mut cmf = [Vec3::new(0.0, 0.0, 0.0); FIT_SAMPLES];
+        let mut xyz_white = Vec3::new(0.0, 0.0, 0.0);
+
+        for (i, c) in cmf.iter_mut().enumerate() {
+            *c = cie_xyz(LAMBDA_MIN + (i as f64) * FIT_STEP);
+            xyz_white = xyz_white + *c * FIT_STEP;
+        }
+
+        let cie_y_integral = xyz_white.y();
+        let white = xyz_to_linear(&(xyz_white / cie_y_integral));
+        let fitter = SigmoidFitter { cmf, cie_y_integral, white };
+
+        let z_nodes: Vec<f64> = (0..TABLE_RES).map(
+            |k| smoothstep(smoothstep((k as f64) / ((TABLE_RES - 1) as f64)))
+        ).collect();
+        let mut coeffs = vec![[0.0; 3]; 3 * TABLE_RES * TABLE_RES * TABLE_RES];
+        let start = TABLE_RES / 5;
+
+        for l in 0..3 {
+            for j in 0..TABLE_RES {
+                let y = (j as f64) / ((TABLE_RES - 1) as f64);
+
+                for i in 0..TABLE_RES {
+                    let x = (i as f64) / ((TABLE_RES - 1) as f64);
+                    let mut fit_range = |ks: &mut dyn Iterator<Item = usize>| {
+                        let mut c = [0.0; 3];
+
+                        for k in ks {
+                            let z = z_nodes[k];
+                            let mut rgb = [0.0; 3];
+
+                            rgb[l] = z;
+                            rgb[(l + 1) % 3] = x * z;
+                            rgb[(l + 2) % 3] = y * z;
+                            fitter.fit(rgb, &mut c);
+                            coeffs[table_idx(l, k, j, i)] = c;
+                        }
+                    };
+
+                    fit_range(&mut (start..TABLE_RES));
+                    fit_range(&mut (0..start).rev());
+                }
+            }
+        }
+
+        SigmoidTable { z_nodes, coeffs, cie_y_integral, white }
+    }
+
+    fn coefficients(&self, rgb: Vec3) -> RgbSigmoid {
+        let rgb = [rgb.x().clamp(0.0, 1.0), rgb.y().clamp(0.0, 1.0), rgb.z().clamp(0.0, 1.0)];
+
+        if rgb[0] == rgb[1] && rgb[1] == rgb[2] {
+            let v = rgb[0];
+            let c2 = if v <= 0.0 {
+                f64::NEG_INFINITY
+            } else if v >= 1.0 {
+                f64::INFINITY
+            } else {
+                (v - 0.5) / (v * (1.0 - v)).sqrt()
+            };
+
+            return RgbSigmoid { c: [0.0, 0.0, c2] };
+        }
+
+        let l = if rgb[0] > rgb[1] {
+            if rgb[0] > rgb[2] { 0 } else { 2 }
+        } else if rgb[1] > rgb[2] { 1 } else { 2 };
+        let z = rgb[l];
+        let res = (TABLE_RES - 1) as f64;
+        let x = rgb[(l + 1) % 3] * res / z;
+        let y = rgb[(l + 2) % 3] * res / z;
+        let xi = (x as usize).min(TABLE_RES - 2);
+        let yi = (y as usize).min(TABLE_RES - 2);
+        let zi = self.z_nodes.partition_point(|n| *n <= z).clamp(1, TABLE_RES - 1) - 1;
+        let dx = x - (xi as f64);
+        let dy = y - (yi as f64);
+        let dz = (z - self.z_nodes[zi]) / (self.z_nodes[zi + 1] - self.z_nodes[zi]);
+        let mut c = [0.0; 3];
+
+        for (n, cn) in c.iter_mut().enumerate() {
+            let co = |dk: usize, dj: usize, di: usize| self.coeffs[table_idx(l, zi + dk, yi + dj, xi + di)][n];
+            let lerp = |t: f64, a: f64, b: f64| (1.0 - t) * a + t * b;
+
+            *cn = lerp(
+                dz,
+                lerp(dy, lerp(dx, co(0, 0, 0), co(0, 0, 1)), lerp(dx, co(0, 1, 0), co(0, 1, 1))),
+                lerp(dy, lerp(dx, co(1, 0, 0), co(1, 0, 1)), lerp(dx, co(1, 1, 0), co(1, 1, 1))),
+            );
+        }
+
+        RgbSigmoid { c }
+    }
+}
+
+fn table_idx(l: usize, k: usize, j: usize, i: usize) -> usize {
+    ((l * TABLE_RES + k) * TABLE_RES + j) * TABLE_RES + i
+}
+
+fn smoothstep(x: f64) -> f64 {
+    x * x * (3.0 - 2.0 * x)
+}
+
+struct SigmoidFitter {
+    cmf: [Vec3; FIT_SAMPLES],
+    cie_y_integral: f64,
+    white: Vec3,
+}
+
+impl SigmoidFitter {
+    fn rgb(&self, c: &[f64; 3]) -> [f64; 3] {
+        let rsp = RgbSigmoid { c: *c };
+        let mut xyz = Vec3::new(0.0, 0.0, 0.0);
+
+        for (i, cmf) in self.cmf.iter().enumerate() {
+            xyz = xyz + *cmf * (rsp.eval(LAMBDA_MIN + (i as f64) * FIT_STEP) * FIT_STEP);
+        }
+
+        let rgb = xyz_to_linear(&(xyz / self.cie_y_integral));
+
+        [rgb.x() / self.white.x(), rgb.y() / self.white.y(), rgb.z() / self.white.z()]
+    }
+
+    fn residual(&self, c: &[f64; 3], target: &[f64; 3]) -> [f64; 3] {
+        let rgb = self.rgb(c);
+
+        [rgb[0] - target[0], rgb[1] - target[1], rgb[2] - target[2]]
+    }
+
+    fn fit(&self, target: [f64; 3], c: &mut [f64; 3]) {
+        for _ in 0..15 {
+            let r = self.residual(c, &target);
+
+            if r.iter().map(|x| x * x).sum::<f64>() < 1e-12 { break; }
+
+            let mut jac = [[0.0; 3]; 3];
+            let eps = 1e-4;
+
+            for n in 0..3 {
+                let mut c0 = *c;
+                let mut c1 = *c;
+
+                c0[n] -= eps;
+                c1[n] += eps;
+
+                let r0 = self.residual(&c0, &target);
+                let r1 = self.residual(&c1, &target);
+
+                for m in 0..3 { jac[m][n] = (r1[m] - r0[m]) / (2.0 * eps); }
+            }
+
+            let Some(step) = solve3(&jac, &r) else { break; };
+
+            for n in 0..3 { c[n] -= step[n]; }
+
+            let max = c.iter().fold(0.0_f64, |a, b| a.max(b.abs()));
+
+            if max > 200.0 {
+                for cn in c.iter_mut() { *cn *= 200.0 / max; }
+            }
+        }
+    }
+}
+
+fn solve3(a: &[[f64; 3]; 3], b: &[f64; 3]) -> Option<[f64; 3]> {
+    let det = |m: &[[f64; 3]; 3]| {
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    };
+    let d = det(a);
+
+    if d.abs() < 1e-15 { return None; }
+
+    let mut x = [0.0; 3];
+
+    for (n, xn) in x.iter_mut().enumerate() {
+        let mut m = *a;
+
+        for r in 0..3 { m[r][n] = b[r]; }
+
+        *xn = det(&m) / d;
+    }
+
+    Some(x)
+}
+
+#[cfg(test)]
+fn integrate_rgb(rgb: Vec3) -> Vec3 {
+    let table = sigmoid_table();
+    let rsp = table.coefficients(rgb);
+    let mut xyz = Vec3::new(0.0, 0.0, 0.0);
+    let mut lambda = LAMBDA_MIN;
+
+    while lambda <= LAMBDA_MAX {
+        xyz = xyz + cie_xyz(lambda) * rsp.eval(lambda);
+        lambda += 1.0;
+    }
+
+    xyz_to_srgb(&(xyz / table.cie_y_integral))
+}
+
+#[test]
+fn test_rgb_round_trip() {
+    let white = integrate_rgb(Vec3::new(1.0, 1.0, 1.0));
+    let color = integrate_rgb(Vec3::new(0.2, 0.5, 0.8));
+
+    assert_approx_eq!(white.x(), 1.0, 0.02);
+    assert_approx_eq!(white.y(), 1.0, 0.02);
+    assert_approx_eq!(white.z(), 1.0, 0.02);
+    assert_approx_eq!(color.x(), 0.2, 0.02);
+    assert_approx_eq!(color.y(), 0.5, 0.02);
+    assert_approx_eq!(color.z(), 0.8, 0.02);
+}
+
+#[test]
+fn test_sample_visible() {
+    let lambda = SampledWavelengths::sample_visible(0.3);
+
+    for i in 0..N_SPECTRUM_SAMPLES {
+        assert!(lambda.lambda[i] >= LAMBDA_MIN && lambda.lambda[i] <= LAMBDA_MAX);
+        assert!(lambda.pdf[i] > 0.0);
+    }
+}
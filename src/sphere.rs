@@ -1,88 +1,185 @@
+use std::f64::consts::PI;
+use serde::{ Serialize, Deserialize };
+
 use crate::vec3::Vec3;
 use crate::ray::Ray;
 use crate::ray::HitRecord;
 use crate::ray::Hittable;
+use crate::ray::BackFace;
+use crate::ray::BackFaceMode;
+use crate::ray::Visibility;
 use crate::materials::Materials;
+use crate::light::LightLink;
+use crate::bump::BumpMap;
+use crate::utils::onb;
+use crate::sampler::PixelSampler;
 
 #[cfg(test)]
 use crate::materials::Lambertian;
+#[cfg(test)]
+use crate::materials::Cutout;
+#[cfg(test)]
+use crate::texture::Textures;
+#[cfg(test)]
+use crate::texture::ImageTexture;
+#[cfg(test)]
+use crate::sampler::Samplers;
+#[cfg(test)]
+use assert_approx_eq::assert_approx_eq;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Sphere {
     pub center: Vec3,
     pub radius: f64,
     pub mat: Materials,
+    #[serde(default)]
+    pub bump: Option<BumpMap>,
+    #[serde(default)]
+    pub back_face: BackFace,
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub visibility: Visibility,
+    #[serde(default)]
+    pub link: LightLink,
 }
 
 impl Sphere {
     pub fn new(center: Vec3, radius: f64, mat: Materials) -> Sphere {
-        Sphere{ center, radius, mat }
+        Sphere{
+            center,
+            radius,
+            mat,
+            bump: None,
+            back_face: BackFace::default(),
+            name: String::new(),
+            visibility: Visibility::default(),
+            link: LightLink::default(),
+        }
+    }
+
+    pub fn with_name(self, name: &str) -> Sphere {
+        Sphere { name: name.to_string(), ..self }
+    }
+
+    pub fn with_visibility(self, visibility: Visibility) -> Sphere {
+        Sphere { visibility, ..self }
+    }
+
+    pub fn with_link(self, link: LightLink) -> Sphere {
+        Sphere { link, ..self }
+    }
+
+    pub fn with_bump(self, bump: BumpMap) -> Sphere {
+        Sphere { bump: Some(bump), ..self }
+    }
+
+    pub fn with_back_face(self, back_face: BackFace) -> Sphere {
+        Sphere { back_face, ..self }
+    }
+}
+
+impl Sphere {
+    fn uv(p: &Vec3) -> (f64, f64) {
+        let theta = (-p.y()).acos();
+        let phi = (-p.z()).atan2(p.x()) + PI;
+
+        (phi / (2.0 * PI), theta / PI)
+    }
+
+    fn hit_at(&self, ray: &Ray, root: f64) -> HitRecord<'_> {
+        let p = ray.at(root);
+        let outward_norm = (p - self.center) / self.radius;
+        let front_face = ray.dir.dot(&outward_norm) < 0.0;
+        let norm = if front_face { outward_norm } else { -outward_norm };
+        let (u, v) = Sphere::uv(&outward_norm);
+        let (dpdu, dpdv) = self.tangents(&outward_norm);
+
+        HitRecord{
+            pt: p,
+            norm,
+            geo_norm: norm,
+            dpdu,
+            dpdv,
+            t: root,
+            u,
+            v,
+            front_face,
+            back_face: self.back_face,
+            mat: &self.mat,
+            name: &self.name,
+        }
+    }
+
+    pub fn shade(&self, hit_record: &mut HitRecord) {
+        let Some(bump) = &self.bump else { return; };
+        let outward_norm = (hit_record.pt - self.center) / self.radius;
+        let ns = bump.shading_normal(hit_record, &outward_norm);
+
+        hit_record.norm = if hit_record.front_face { ns } else { -ns };
+    }
+
+    pub fn sample_solid_angle(&self, p: &Vec3, u: (f64, f64)) -> Option<(Vec3, f64)> {
+        let to_center = self.center - *p;
+        let dist_sqr = to_center.len_sqr();
+
+        if dist_sqr <= self.radius * self.radius { return None; }
+
+        let cos_max = (1.0 - self.radius * self.radius / dist_sqr).max(0.0).sqrt();
+        let cos_theta = 1.0 - u.0 * (1.0 - cos_max);
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * u.1;
+        let axis = to_center.unit_vec();
+        let (t, b) = onb(&axis);
+        let wi = t * (sin_theta * phi.cos()) + b * (sin_theta * phi.sin()) + axis * cos_theta;
+
+        Some((wi, 1.0 / (2.0 * PI * (1.0 - cos_max))))
+    }
+
+    pub fn solid_angle_pdf(&self, p: &Vec3) -> f64 {
+        let dist_sqr = (self.center - *p).len_sqr();
+
+        if dist_sqr <= self.radius * self.radius { return 0.0; }
+
+        let cos_max = (1.0 - self.radius * self.radius / dist_sqr).max(0.0).sqrt();
+
+        1.0 / (2.0 * PI * (1.0 - cos_max))
+    }
+
+    fn tangents(&self, n: &Vec3) -> (Vec3, Vec3) {
+        let sin_theta = (1.0 - n.y() * n.y()).max(0.0).sqrt();
+        let dpdu = Vec3::new(n.z(), 0.0, -n.x()) * (2.0 * PI * self.radius);
+
+        if sin_theta < 1e-8 { return (dpdu, Vec3::new(0.0, 0.0, 0.0)); }
+
+        let dpdv = Vec3::new(-n.y() * n.x() / sin_theta, sin_theta, -n.y() * n.z() / sin_theta) * (PI * self.radius);
+
+        (dpdu, dpdv)
     }
 }
 
 impl Hittable for Sphere {
-    fn hit(&self, ray: &Ray, ray_tmin: f64, ray_tmax: f64) -> Option<HitRecord> {
+    fn hit(&self, ray: &Ray, ray_tmin: f64, ray_tmax: f64, sampler: &PixelSampler) -> Option<HitRecord<'_>> {
         let oc = self.center - ray.orig;
         let a = ray.dir.len_sqr();
         let h = oc.dot(&ray.dir);
         let c = oc.len_sqr() - self.radius * self.radius;
         let discriminant = h * h - a * c;
 
-        if discriminant > 0.0 {
-            let sqrtd = discriminant.sqrt();
-            let root1 = (h - sqrtd) / a;
-            let root2 = (h + sqrtd) / a;
-
-            if root1 <= ray_tmax && root1 >= ray_tmin {
-                let p = ray.at(root1);
-                let mut norm = (p - self.center) / self.radius;
-                let front_face = if ray.dir.dot(&norm) < 0.0 { true } else { false };
-                
-                norm = if front_face { norm } else { -norm };
-
-                return Some(HitRecord{
-                    pt: p,
-                    norm,
-                    t: root1,
-                    front_face,
-                    mat: &self.mat,
-                })
-            }
-
-            if root2 <= ray_tmax && root2 >= ray_tmin {
-                let p = ray.at(root2);
-                let mut norm = (p - self.center) / self.radius;
-                let front_face = if ray.dir.dot(&norm) < 0.0 { true } else { false };
-
-                norm = if front_face { norm } else { -norm };
-
-                return Some(HitRecord{
-                    pt: p,
-                    norm,
-                    t: root2,
-                    front_face,
-                    mat: &self.mat,
-                })
-            }
-        } else if discriminant == 0.0 {
-            let sqrtd = discriminant.sqrt();
-            let root = (h - sqrtd) / a;
-
-            if root <= ray_tmax && root >= ray_tmin {
-                let p = ray.at(root);
-                let mut norm = (p - self.center) / self.radius;
-                let front_face = if ray.dir.dot(&norm) < 0.0 { true } else { false };
-
-                norm = if front_face { norm } else { -norm };
-
-                return Some(HitRecord{
-                    pt: p,
-                    norm,
-                    t: root,
-                    front_face,
-                    mat: &self.mat,
-                })
-            }
+        if discriminant < 0.0 { return None; }
+
+        let sqrtd = discriminant.sqrt();
+
+        for root in [(h - sqrtd) / a, (h + sqrtd) / a] {
+            if root > ray_tmax || root < ray_tmin { continue; }
+
+            let hit_record = self.hit_at(ray, root);
+
+            if !hit_record.front_face && self.back_face.mode == BackFaceMode::Cull { continue; }
+
+            if hit_record.mat.is_cut_out(&hit_record, sampler) { continue; }
+
+            return Some(hit_record);
         }
 
         None
@@ -91,6 +188,7 @@ impl Hittable for Sphere {
 
 #[test]
 fn test_hit() {
+    let sampler = PixelSampler::new(Samplers::default(), 0, (0, 0), 0);
     let sphere = Sphere::new(
         Vec3::new(0.0, 0.0, 0.0),
         1.0,
@@ -100,7 +198,116 @@ fn test_hit() {
         Vec3::new(0.0, 0.0, -5.0),
         Vec3::new(0.0, 0.0, 1.0),
     );
-    let hit = sphere.hit(&ray, 0.0, f64::INFINITY);
+    let hit = sphere.hit(&ray, 0.0, f64::INFINITY, &sampler);
 
     assert_eq!(hit.unwrap().t, 4.0);
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_flat_normal_map() {
+    let sampler = PixelSampler::new(Samplers::default(), 0, (0, 0), 0);
+    let flat = Textures::Image(ImageTexture::from_pixels(1, 1, vec![Vec3::new(0.5, 0.5, 1.0)]));
+    let sphere = Sphere::new(
+        Vec3::new(0.0, 0.0, 0.0),
+        1.0,
+        Materials::Lambertian(Lambertian::new(Vec3::new(0.0, 0.0, 0.0))),
+    ).with_bump(BumpMap::Normal { map: flat });
+    let ray = Ray::new(
+        Vec3::new(0.3, 0.2, -5.0),
+        Vec3::new(0.0, 0.0, 1.0),
+    );
+    let mut hit = sphere.hit(&ray, 0.0, f64::INFINITY, &sampler).unwrap();
+
+    sphere.shade(&mut hit);
+
+    assert_approx_eq!(hit.dpdu.dot(&hit.geo_norm), 0.0);
+    assert_approx_eq!(hit.dpdv.dot(&hit.geo_norm), 0.0);
+    assert_approx_eq!(hit.norm.dot(&hit.geo_norm), 1.0);
+}
+
+#[test]
+fn test_bump_deferred_to_shade() {
+    let sampler = PixelSampler::new(Samplers::default(), 0, (0, 0), 0);
+    let tilted = Textures::Image(ImageTexture::from_pixels(1, 1, vec![Vec3::new(1.0, 0.5, 0.5)]));
+    let sphere = Sphere::new(
+        Vec3::new(0.0, 0.0, 0.0),
+        1.0,
+        Materials::Lambertian(Lambertian::new(Vec3::new(0.0, 0.0, 0.0))),
+    ).with_bump(BumpMap::Normal { map: tilted });
+    let ray = Ray::new(
+        Vec3::new(0.3, 0.2, -5.0),
+        Vec3::new(0.0, 0.0, 1.0),
+    );
+    let mut hit = sphere.hit(&ray, 0.0, f64::INFINITY, &sampler).unwrap();
+
+    assert_approx_eq!(hit.norm.dot(&hit.geo_norm), 1.0);
+
+    sphere.shade(&mut hit);
+
+    assert!(hit.norm.dot(&hit.geo_norm) < 0.5);
+}
+
+#[test]
+fn test_cutout_hit() {
+    let sampler = PixelSampler::new(Samplers::default(), 0, (0, 0), 0);
+    let lambertian = Materials::Lambertian(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)));
+    let clear = Sphere::new(
+        Vec3::new(0.0, 0.0, 0.0),
+        1.0,
+        Materials::Cutout(Cutout::new(lambertian.clone(), Vec3::new(0.0, 0.0, 0.0).into(), 0.5)),
+    );
+    let opaque = Sphere::new(
+        Vec3::new(0.0, 0.0, 0.0),
+        1.0,
+        Materials::Cutout(Cutout::new(lambertian, Vec3::new(1.0, 1.0, 1.0).into(), 0.5)),
+    );
+    let ray = Ray::new(
+        Vec3::new(0.0, 0.0, -5.0),
+        Vec3::new(0.0, 0.0, 1.0),
+    );
+
+    assert!(clear.hit(&ray, 0.0, f64::INFINITY, &sampler).is_none());
+    assert_eq!(opaque.hit(&ray, 0.0, f64::INFINITY, &sampler).unwrap().t, 4.0);
+}
+
+#[test]
+fn test_stochastic_cutout_hit() {
+    let lambertian = Materials::Lambertian(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)));
+    let sphere = Sphere::new(
+        Vec3::new(0.0, 0.0, 0.0),
+        1.0,
+        Materials::Cutout(Cutout::stochastic(lambertian, Vec3::new(0.5, 0.5, 0.5).into())),
+    );
+    let p = Vec3::new(0.3, 0.2, -(1.0_f64 - 0.13).sqrt());
+    let camera = Ray::new(p - Vec3::new(0.0, 0.0, 4.0), Vec3::new(0.0, 0.0, 1.0));
+    let shadow = Ray::new(p - Vec3::new(-1.0, 1.0, 3.0), Vec3::new(-1.0, 1.0, 3.0));
+    let (mut hits, mut reseeded) = (0, 0);
+    let n = 4000;
+
+    for k in 0..n {
+        let sampler = PixelSampler::new(Samplers::default(), 0, (0, 0), k);
+        let hit = sphere.hit(&camera, 0.0, 4.5, &sampler).is_some();
+
+        assert_eq!(sphere.hit(&shadow, 0.0, 1.5, &sampler).is_some(), hit);
+
+        if hit { hits += 1; }
+        if hit != sphere.hit(&camera, 0.0, 4.5, &PixelSampler::new(Samplers::default(), 1, (0, 0), k)).is_some() { reseeded += 1; }
+    }
+
+    assert_approx_eq!((hits as f64) / (n as f64), 0.5, 0.03);
+    assert!(reseeded > n / 4);
+}
+
+#[test]
+fn test_back_face() {
+    let sampler = PixelSampler::new(Samplers::default(), 0, (0, 0), 0);
+    let mat = Materials::Lambertian(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)));
+    let culled = Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0, mat.clone()).with_back_face(BackFace::new(BackFaceMode::Cull, true));
+    let black = Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0, mat).with_back_face(BackFace::new(BackFaceMode::Black, true));
+    let inside = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0));
+    let outside = Ray::new(Vec3::new(0.0, 0.0, -5.0), Vec3::new(0.0, 0.0, 1.0));
+
+    assert!(culled.hit(&inside, 0.001, f64::INFINITY, &sampler).is_none());
+    assert_eq!(culled.hit(&outside, 0.001, f64::INFINITY, &sampler).unwrap().t, 4.0);
+    assert!(!black.hit(&inside, 0.001, f64::INFINITY, &sampler).unwrap().front_face);
+}
@@ -0,0 +1,143 @@
+use serde::{ Serialize, Deserialize };
+
+use crate::vec3::Vec3;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Textures {
+    Solid(SolidColor),
+    Checker(Checker),
+    Image(ImageTexture),
+}
+
+pub trait Texture {
+    fn value(&self, u: f64, v: f64, p: &Vec3) -> Vec3;
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct SolidColor {
+    pub albedo: Vec3,
+}
+
+impl SolidColor {
+    pub fn new(albedo: Vec3) -> SolidColor {
+        SolidColor { albedo }
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f64, _v: f64, _p: &Vec3) -> Vec3 {
+        self.albedo
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Checker {
+    pub scale: f64,
+    pub even: Box<Textures>,
+    pub odd: Box<Textures>,
+}
+
+impl Checker {
+    pub fn new(scale: f64, even: Textures, odd: Textures) -> Checker {
+        Checker { scale, even: Box::new(even), odd: Box::new(odd) }
+    }
+}
+
+impl Texture for Checker {
+    fn value(&self, u: f64, v: f64, p: &Vec3) -> Vec3 {
+        let inv_scale = 1.0 / self.scale;
+        let x = (p.x() * inv_scale).floor() as i64;
+        let y = (p.y() * inv_scale).floor() as i64;
+        let z = (p.z() * inv_scale).floor() as i64;
+
+        if (x + y + z) % 2 == 0 { self.even.value(u, v, p) } else { self.odd.value(u, v, p) }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "ImageSource", into = "ImageSource")]
+pub struct ImageTexture {
+    pub path: String,
+    width: usize,
+    height: usize,
+    pixels: Vec<Vec3>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ImageSource {
+    image: String,
+}
+
+impl ImageTexture {
+    pub fn load(path: &str) -> Result<ImageTexture, image::ImageError> {
+        let img = image::open(path)?.to_rgb();
+        let (width, height) = img.dimensions();
+        let pixels = img.into_raw().chunks(3).map(
+            |p| Vec3::new(p[0] as f64, p[1] as f64, p[2] as f64) / 255.0
+        ).collect();
+
+        Ok(ImageTexture { path: path.to_string(), width: width as usize, height: height as usize, pixels })
+    }
+
+    pub fn from_pixels(width: usize, height: usize, pixels: Vec<Vec3>) -> ImageTexture {
+        ImageTexture { path: String::new(), width, height, pixels }
+    }
+}
+
+impl TryFrom<ImageSource> for ImageTexture {
+    type Error = String;
+
+    fn try_from(source: ImageSource) -> Result<ImageTexture, String> {
+        ImageTexture::load(&source.image).map_err(|e| format!("err loading {}: {}", source.image, e))
+    }
+}
+
+impl From<ImageTexture> for ImageSource {
+    fn from(texture: ImageTexture) -> ImageSource {
+        ImageSource { image: texture.path }
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _p: &Vec3) -> Vec3 {
+        if self.pixels.is_empty() { return Vec3::new(0.0, 1.0, 1.0); }
+
+        let u = u.clamp(0.0, 1.0);
+        let v = 1.0 - v.clamp(0.0, 1.0);
+        let i = ((u * self.width as f64) as usize).min(self.width - 1);
+        let j = ((v * self.height as f64) as usize).min(self.height - 1);
+
+        self.pixels[j * self.width + i]
+    }
+}
+
+impl From<Vec3> for Textures {
+    fn from(albedo: Vec3) -> Textures {
+        Textures::Solid(SolidColor::new(albedo))
+    }
+}
+
+impl Texture for Textures {
+    fn value(&self, u: f64, v: f64, p: &Vec3) -> Vec3 {
+        match self {
+            Textures::Solid(s) => { s.value(u, v, p) }
+            Textures::Checker(c) => { c.value(u, v, p) }
+            Textures::Image(i) => { i.value(u, v, p) }
+        }
+    }
+}
+
+#[test]
+fn test_checker() {
+    let checker = Textures::Checker(Checker::new(
+        1.0,
+        Vec3::new(1.0, 1.0, 1.0).into(),
+        Vec3::new(0.0, 0.0, 0.0).into(),
+    ));
+
+    assert_eq!(checker.value(0.0, 0.0, &Vec3::new(0.5, 0.5, 0.5)).x(), 1.0);
+    assert_eq!(checker.value(0.0, 0.0, &Vec3::new(1.5, 0.5, 0.5)).x(), 0.0);
+    assert_eq!(checker.value(0.0, 0.0, &Vec3::new(-0.5, 0.5, 0.5)).x(), 0.0);
+}
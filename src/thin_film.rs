@@ -0,0 +1,139 @@
+use serde::{ Serialize, Deserialize };
+
+use crate::vec3::Vec3;
+use crate::ray::HitRecord;
+use crate::texture::Textures;
+use crate::texture::Texture;
+use crate::spectrum::SampledWavelengths;
+use crate::spectrum::Wavelengths;
+use crate::spectrum::cie_xyz;
+use crate::spectrum::xyz_to_srgb;
+use crate::spectrum::N_SPECTRUM_SAMPLES;
+
+#[cfg(test)]
+use assert_approx_eq::assert_approx_eq;
+#[cfg(test)]
+use crate::utils::test_hit_record;
+#[cfg(test)]
+use crate::materials::Materials;
+#[cfg(test)]
+use crate::materials::Glass;
+
+const FILM_LAMBDA_MIN: f64 = 380.0;
+const FILM_LAMBDA_MAX: f64 = 780.0;
+const FILM_SAMPLES: usize = 4 * N_SPECTRUM_SAMPLES;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThinFilm {
+    pub thickness: f64,
+    pub ior: f64,
+    #[serde(default)]
+    pub thickness_map: Option<Textures>,
+}
+
+impl ThinFilm {
+    pub fn new(thickness: f64, ior: f64) -> ThinFilm {
+        ThinFilm { thickness, ior, thickness_map: None }
+    }
+
+    pub fn textured(thickness: f64, ior: f64, thickness_map: Textures) -> ThinFilm {
+        ThinFilm { thickness, ior, thickness_map: Some(thickness_map) }
+    }
+
+    fn thickness_at(&self, hit_record: &HitRecord) -> f64 {
+        match &self.thickness_map {
+            Some(map) => self.thickness * map.value(hit_record.u, hit_record.v, &hit_record.pt).x(),
+            None => self.thickness,
+        }
+    }
+
+    pub fn dielectric_reflectance(&self, cos_theta_i: f64, eta_i: f64, eta_t: f64, hit_record: &HitRecord) -> Vec3 {
+        let d = self.thickness_at(hit_record);
+
+        integrate_rgb(|lambda, _| {
+            let Some(cos_film) = snell(cos_theta_i, eta_i, self.ior) else { return 1.0; };
+            let Some(cos_t) = snell(cos_theta_i, eta_i, eta_t) else { return 1.0; };
+            let (r12_s, r12_p) = amplitudes(cos_theta_i, cos_film, eta_i, self.ior);
+            let (r23_s, r23_p) = amplitudes(cos_film, cos_t, self.ior, eta_t);
+            let delta = phase(lambda, d, self.ior, cos_film);
+
+            (airy(r12_s, r23_s, delta) + airy(r12_p, r23_p, delta)) / 2.0
+        }, None)
+    }
+
+    pub fn conductor_reflectance(&self, cos_theta_i: f64, albedo: Vec3, hit_record: &HitRecord) -> Vec3 {
+        let d = self.thickness_at(hit_record);
+
+        integrate_rgb(|lambda, substrate| {
+            let Some(cos_film) = snell(cos_theta_i, 1.0, self.ior) else { return 1.0; };
+            let (r12_s, r12_p) = amplitudes(cos_theta_i, cos_film, 1.0, self.ior);
+            let r23 = -substrate.clamp(0.0, 1.0).sqrt();
+            let delta = phase(lambda, d, self.ior, cos_film);
+
+            (airy(r12_s, r23, delta) + airy(r12_p, r23, delta)) / 2.0
+        }, Some(albedo))
+    }
+}
+
+fn snell(cos_theta_i: f64, eta_i: f64, eta_t: f64) -> Option<f64> {
+    let sin2_t = (eta_i / eta_t).powi(2) * (1.0 - cos_theta_i * cos_theta_i).max(0.0);
+
+    if sin2_t >= 1.0 { return None; }
+
+    Some((1.0 - sin2_t).sqrt())
+}
+
+fn amplitudes(cos_i: f64, cos_t: f64, eta_i: f64, eta_t: f64) -> (f64, f64) {
+    let r_s = (eta_i * cos_i - eta_t * cos_t) / (eta_i * cos_i + eta_t * cos_t);
+    let r_p = (eta_t * cos_i - eta_i * cos_t) / (eta_t * cos_i + eta_i * cos_t);
+
+    (r_s, r_p)
+}
+
+fn phase(lambda: f64, thickness: f64, ior: f64, cos_film: f64) -> f64 {
+    4.0 * std::f64::consts::PI * ior * thickness * cos_film / lambda
+}
+
+fn airy(r12: f64, r23: f64, delta: f64) -> f64 {
+    let cross = 2.0 * r12 * r23 * delta.cos();
+
+    ((r12 * r12 + r23 * r23 + cross) / (1.0 + r12 * r12 * r23 * r23 + cross)).clamp(0.0, 1.0)
+}
+
+fn integrate_rgb(reflectance: impl Fn(f64, f64) -> f64, substrate: Option<Vec3>) -> Vec3 {
+    let step = (FILM_LAMBDA_MAX - FILM_LAMBDA_MIN) / (FILM_SAMPLES as f64);
+    let mut xyz = Vec3::new(0.0, 0.0, 0.0);
+    let mut y_integral = 0.0;
+
+    for batch in 0..FILM_SAMPLES / N_SPECTRUM_SAMPLES {
+        let mut lambda = SampledWavelengths { lambda: [0.0; N_SPECTRUM_SAMPLES], pdf: [1.0; N_SPECTRUM_SAMPLES] };
+
+        for (i, l) in lambda.lambda.iter_mut().enumerate() {
+            *l = FILM_LAMBDA_MIN + ((batch * N_SPECTRUM_SAMPLES + i) as f64 + 0.5) * step;
+        }
+
+        let substrate = lambda.upsample(substrate.unwrap_or(Vec3::new(1.0, 1.0, 1.0))).values();
+
+        for (l, s) in lambda.lambda.iter().zip(substrate.iter()) {
+            let cmf = cie_xyz(*l);
+
+            xyz = xyz + cmf * reflectance(*l, *s);
+            y_integral += cmf.y();
+        }
+    }
+
+    xyz_to_srgb(&(xyz / y_integral))
+}
+
+#[test]
+fn test_thin_film() {
+    let mat = Materials::Glass(Glass::new(1.5));
+    let hit_record = test_hit_record(&mat);
+    let bare = ThinFilm::new(0.0, 1.33).dielectric_reflectance(1.0, 1.0, 1.5, &hit_record);
+    let film = ThinFilm::new(300.0, 1.33).dielectric_reflectance(1.0, 1.0, 1.5, &hit_record);
+
+    assert_approx_eq!(bare.x(), 0.04, 0.005);
+    assert_approx_eq!(bare.y(), 0.04, 0.005);
+    assert_approx_eq!(bare.z(), 0.04, 0.005);
+    assert!((film.x() - film.y()).abs() > 0.005 || (film.y() - film.z()).abs() > 0.005);
+}
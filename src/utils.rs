@@ -1,14 +1,60 @@
 use image::png::PNGEncoder;
 use image::ColorType;
 use std::fs::File;
-use rand::Rng;
 
 use crate::materials::Scatterable;
 use crate::vec3::Vec3;
 use crate::ray::Ray;
 use crate::ray::HitRecord;
+use crate::ray::BackFaceMode;
 use crate::ray::Hittable;
+use crate::ray::RayKind;
 use crate::sphere::Sphere;
+use crate::scene::Scene;
+use crate::spectrum::Wavelengths;
+use crate::medium::MediumEvent;
+use crate::medium::HomogeneousMedium;
+use crate::light::Light;
+use crate::light::LightSample;
+use crate::light::LightLink;
+use crate::light::Lights;
+use crate::light_tree::TreeLight;
+use crate::light_tree::LightTree;
+use crate::sampler::PixelSampler;
+use crate::sampler::LIGHT_SELECT_DIMENSION;
+use crate::sampler::LIGHT_DIMENSION;
+use crate::sampler::BSDF_DIMENSION;
+use crate::sampler::DIRECTIONAL_DIMENSION;
+use crate::sampler::SKY_DIMENSION;
+use crate::sampler::MEDIUM_DIMENSION;
+use crate::sampler::MATERIAL_DIMENSION;
+
+#[cfg(test)]
+use assert_approx_eq::assert_approx_eq;
+#[cfg(test)]
+use crate::materials::Materials;
+#[cfg(test)]
+use crate::materials::Lambertian;
+#[cfg(test)]
+use crate::light::PointLight;
+#[cfg(test)]
+use crate::light::DirectionalLight;
+#[cfg(test)]
+use crate::spectrum::Rgb;
+#[cfg(test)]
+use crate::spectrum::SampledWavelengths;
+#[cfg(test)]
+use crate::ray::Visibility;
+#[cfg(test)]
+use crate::sampler::Samplers;
+#[cfg(test)]
+use crate::ray::BackFace;
+#[cfg(test)]
+use crate::materials::DiffuseLight;
+#[cfg(test)]
+use crate::sky::Sky;
+#[cfg(test)]
+use crate::sky::SkyParams;
 
 pub fn write_img(
     filename: &str,
@@ -35,90 +81,337 @@ pub fn clamp(x: f64) -> f64 {
     x
 }
 
-pub fn sample_sqr() -> Vec3 {
-    let mut rng = rand::thread_rng();
-
-    Vec3::new(
-        rng.gen_range(-0.5..0.5),
-        rng.gen_range(-0.5..0.5),
-        0.0,
-    )
-}
-
-pub fn random_in_unit_disk() -> Vec3 {
-    loop {
-        let mut rng = rand::thread_rng();
-        let p = Vec3::new(
-            rng.gen_range(-1.0..1.0),
-            rng.gen_range(-1.0..1.0),
-            0.0,
-        );
+pub fn random_in_unit_disk(sampler: &mut PixelSampler) -> Vec3 {
+    let (u, v) = sampler.get_2d();
+    let r = u.sqrt();
+    let theta = 2.0 * std::f64::consts::PI * v;
 
-        if p.len_sqr() < 1.0 { return p; }
-    }
+    Vec3::new(r * theta.cos(), r * theta.sin(), 0.0)
 }
 
-fn hit_record<'materials>(hit_world: &'materials Vec<Sphere>, ray: &Ray, ray_tmin: f64, ray_tmax: f64) -> Option<HitRecord<'materials>> {
+fn hit_record<'materials>(
+    hit_world: &'materials [Sphere],
+    ray: &Ray,
+    ray_tmin: f64,
+    ray_tmax: f64,
+    kind: RayKind,
+    link: Option<&LightLink>,
+    sampler: &PixelSampler,
+) -> Option<(usize, HitRecord<'materials>)> {
     let mut closest = ray_tmax;
     let mut hit_record = None;
 
-    for sphere in hit_world {
-        if let Some(hit) = sphere.hit(&ray, ray_tmin, closest) {
+    for (i, sphere) in hit_world.iter().enumerate() {
+        if !sphere.visibility.visible(kind) { continue; }
+        if link.is_some_and(|l| !l.casts_shadow(&sphere.name)) { continue; }
+
+        if let Some(hit) = sphere.hit(ray, ray_tmin, closest, sampler) {
             closest = hit.t;
-            hit_record = Some(hit);
+            hit_record = Some((i, hit));
         }
     }
 
     hit_record
 }
 
-pub fn ray_color(ray: &Ray, hit_world: &Vec<Sphere>, depth: i32) -> Vec3 {
-    if depth <= 0 { return Vec3::new(0.0, 0.0, 0.0); }
+#[derive(Debug, Clone, Copy)]
+struct Bounce<'a> {
+    pt: Vec3,
+    norm: Vec3,
+    pdf: f64,
+    name: &'a str,
+}
+
+fn linked(link: &LightLink, prev: Option<Bounce>) -> bool {
+    prev.is_none_or(|p| link.illuminates(p.name))
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Vertex<'a> {
+    pt: Vec3,
+    norm: Vec3,
+    name: &'a str,
+    medium: Option<HomogeneousMedium>,
+}
+
+impl Vertex<'_> {
+    fn transmittance(&self, dist: f64) -> Vec3 {
+        self.medium.map_or(Vec3::new(1.0, 1.0, 1.0), |m| m.transmittance(dist))
+    }
+}
+
+fn surface_bsdf(ray: &Ray, hit: &HitRecord, wi: &Vec3) -> Option<(Vec3, f64)> {
+    let wo = -ray.dir;
+    let shading_side = wi.dot(&hit.norm) * wo.dot(&hit.norm) > 0.0;
+    let geometric_side = wi.dot(&hit.geo_norm) * wo.dot(&hit.geo_norm) > 0.0;
+
+    if shading_side != geometric_side { return None; }
+
+    let f = hit.mat.eval(ray, hit, wi);
+
+    if f.near_zero() { return None; }
+
+    Some((f, hit.mat.scatter_pdf(ray, hit, wi)))
+}
+
+fn occluded(scene: &Scene, vertex: &Vertex, sample: &LightSample, link: Option<&LightLink>, sampler: &PixelSampler) -> bool {
+    let shadow_ray = Ray::new(vertex.pt, sample.wi);
+
+    hit_record(&scene.objects, &shadow_ray, 0.001, sample.dist * (1.0 - 1e-4), RayKind::Shadow, link, sampler).is_some()
+}
+
+fn light_contribution<W: Wavelengths>(lambda: &W, vertex: &Vertex, f: Vec3, sample: &LightSample, weight: f64) -> W::Radiance {
+    lambda.upsample(f) * lambda.upsample(vertex.transmittance(sample.dist)) * lambda.upsample(sample.radiance) * (weight / sample.pdf)
+}
+
+fn sample_emitter(sphere: &Sphere, pt: &Vec3, sampler: &mut PixelSampler) -> Option<LightSample> {
+    let (wi, pdf) = sphere.sample_solid_angle(pt, sampler.get_2d())?;
+    let ray = Ray::new(*pt, wi);
+    let hit = sphere.hit(&ray, 0.0, f64::INFINITY, sampler)?;
+
+    if !hit.front_face && !hit.back_face.emit { return None; }
+
+    let (None, radiance) = hit.mat.scatter(&ray, &hit, sampler)? else { return None; };
+
+    Some(LightSample { wi, dist: hit.t, radiance, pdf })
+}
+
+fn direct_light<W: Wavelengths>(
+    scene: &Scene,
+    light_tree: &LightTree,
+    vertex: &Vertex,
+    bsdf: impl Fn(&Vec3) -> Option<(Vec3, f64)>,
+    lambda: &W,
+    sampler: &mut PixelSampler,
+) -> W::Radiance {
+    let mut radiance = lambda.upsample(Vec3::new(0.0, 0.0, 0.0));
+
+    sampler.start_bounce_dimension(LIGHT_SELECT_DIMENSION, 1);
+
+    let u = sampler.get_1d();
+
+    sampler.start_bounce_dimension(LIGHT_DIMENSION, 2);
+
+    match light_tree.sample(&vertex.pt, &vertex.norm, u) {
+        Some((TreeLight::Light(i), pmf)) => {
+            let light = &scene.lights[i];
+
+            if let (true, Some(sample)) = (light.link().illuminates(vertex.name), light.sample_li(&vertex.pt, sampler)) {
+                if let Some((f, _)) = bsdf(&sample.wi).filter(|_| !occluded(scene, vertex, &sample, Some(light.link()), sampler)) {
+                    radiance = radiance + light_contribution(lambda, vertex, f, &sample, 1.0 / pmf);
+                }
+            }
+        }
+        Some((TreeLight::Object(i), pmf)) => {
+            let link = &scene.objects[i].link;
+
+            if let (true, Some(sample)) = (link.illuminates(vertex.name), sample_emitter(&scene.objects[i], &vertex.pt, sampler)) {
+                if let Some((f, bsdf_pdf)) = bsdf(&sample.wi).filter(|(_, pdf)| *pdf > 0.0 && !occluded(scene, vertex, &sample, Some(link), sampler)) {
+                    radiance = radiance + light_contribution(lambda, vertex, f, &sample, power_heuristic(pmf * sample.pdf, bsdf_pdf) / pmf);
+                }
+            }
+        }
+        None => {}
+    }
+
+    for (k, light) in scene.lights.iter().filter(|l| matches!(l, Lights::Directional(_))).enumerate() {
+        if !light.link().illuminates(vertex.name) { continue; }
+
+        // Only the first directional light owns the reserved dimensions, the rest overflow.
+        sampler.start_bounce_dimension(DIRECTIONAL_DIMENSION, if k == 0 { 2 } else { 0 });
+
+        let Some(sample) = light.sample_li(&vertex.pt, sampler) else { continue; };
+
+        if let Some((f, _)) = bsdf(&sample.wi).filter(|_| !occluded(scene, vertex, &sample, Some(light.link()), sampler)) {
+            radiance = radiance + light_contribution(lambda, vertex, f, &sample, 1.0);
+        }
+    }
+
+    if let Some(sky) = &scene.sky {
+        sampler.start_bounce_dimension(SKY_DIMENSION, 3);
+
+        if let (true, Some(sample)) = (sky.params.link.illuminates(vertex.name), sky.sample_li(&vertex.pt, sampler)) {
+            if let Some((f, bsdf_pdf)) = bsdf(&sample.wi).filter(|(_, pdf)| *pdf > 0.0 && !occluded(scene, vertex, &sample, Some(&sky.params.link), sampler)) {
+                radiance = radiance + light_contribution(lambda, vertex, f, &sample, power_heuristic(sample.pdf, bsdf_pdf));
+            }
+        }
+    }
+
+    radiance
+}
+
+fn emission_weight(scene: &Scene, light_tree: &LightTree, index: usize, prev: Option<Bounce>) -> f64 {
+    let Some(prev) = prev.filter(|p| p.pdf > 0.0) else { return 1.0; };
+    let pmf = light_tree.pmf(&prev.pt, &prev.norm, TreeLight::Object(index));
+
+    if pmf <= 0.0 { return 1.0; }
+
+    power_heuristic(prev.pdf, pmf * scene.objects[index].solid_angle_pdf(&prev.pt))
+}
+
+fn power_heuristic(f_pdf: f64, g_pdf: f64) -> f64 {
+    let f2 = f_pdf * f_pdf;
+    let g2 = g_pdf * g_pdf;
+
+    if f2.is_infinite() { return 1.0; }
+
+    f2 / (f2 + g2)
+}
+
+fn background(scene: &Scene, ray: &Ray, prev: Option<Bounce>) -> Vec3 {
+    let unit_dir = ray.dir.unit_vec();
+
+    let Some(sky) = &scene.sky else {
+        let a = 0.5 * (unit_dir.y() + 1.0);
+
+        return Vec3::new(1.0, 1.0, 1.0) * (1.0 - a) + Vec3::new(0.5, 0.7, 1.0) * a;
+    };
+
+    if !linked(&sky.params.link, prev) { return Vec3::new(0.0, 0.0, 0.0); }
+
+    match prev.filter(|p| p.pdf > 0.0) {
+        Some(prev) => sky.radiance(&unit_dir) * power_heuristic(prev.pdf, sky.pdf(&unit_dir)),
+        None => sky.radiance(&unit_dir),
+    }
+}
+
+#[cfg(test)]
+fn ray_color<W: Wavelengths>(ray: &Ray, scene: &Scene, depth: i32, lambda: &W, sampler: &mut PixelSampler) -> W::Radiance {
+    ray_color_with_tree(ray, scene, &scene.light_tree(), depth, lambda, sampler)
+}
+
+pub fn ray_color_with_tree<W: Wavelengths>(
+    ray: &Ray,
+    scene: &Scene,
+    light_tree: &LightTree,
+    depth: i32,
+    lambda: &W,
+    sampler: &mut PixelSampler,
+) -> W::Radiance {
+    trace(ray, scene, light_tree, depth, lambda, RayKind::Camera, None, None, sampler)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn trace<W: Wavelengths>(
+    ray: &Ray,
+    scene: &Scene,
+    light_tree: &LightTree,
+    depth: i32,
+    lambda: &W,
+    kind: RayKind,
+    prev: Option<Bounce>,
+    medium: Option<HomogeneousMedium>,
+    sampler: &mut PixelSampler,
+) -> W::Radiance {
+    if depth <= 0 { return lambda.upsample(Vec3::new(0.0, 0.0, 0.0)); }
+
+    sampler.start_bounce();
 
     let hit = hit_record(
-        &hit_world,
-        &ray,
+        &scene.objects,
+        ray,
         0.001,
         f64::MAX,
+        kind,
+        None,
+        sampler,
     );
+    let mut transmittance = Vec3::new(1.0, 1.0, 1.0);
+
+    if let Some(medium) = &medium {
+        sampler.start_bounce_dimension(MEDIUM_DIMENSION, 4);
+
+        match medium.sample(ray, hit.as_ref().map_or(f64::INFINITY, |(_, h)| h.t), sampler) {
+            MediumEvent::Scatter(sr, weight) => {
+                let wo = ray.dir.unit_vec();
+                let vertex = Vertex { pt: sr.orig, norm: Vec3::new(0.0, 0.0, 0.0), name: "", medium: Some(*medium) };
+                let phase = |wi: &Vec3| {
+                    let p = medium.phase(&wo, wi);
+
+                    Some((Vec3::new(p, p, p), p))
+                };
+                let direct = direct_light(scene, light_tree, &vertex, phase, lambda, sampler);
+                let bounce = Bounce { pt: sr.orig, norm: vertex.norm, pdf: medium.phase(&wo, &sr.dir), name: "" };
+                let color = trace(&sr, scene, light_tree, depth - 1, lambda, RayKind::Diffuse, Some(bounce), Some(*medium), sampler);
+
+                return (color + direct) * lambda.upsample(weight);
+            }
+            MediumEvent::Pass(weight) => {
+                transmittance = weight;
+            }
+        }
+    }
 
     match hit {
-        Some(hit_record) => {
-            let scattered = hit_record.mat.scatter(&ray, &hit_record);
+        Some((index, mut hit_record)) => {
+            scene.objects[index].shade(&mut hit_record);
+
+            sampler.start_bounce_dimension(MATERIAL_DIMENSION, 1);
+
+            hit_record.mat = hit_record.mat.resolve(sampler.get_1d(), &hit_record);
+
+            if !hit_record.front_face && hit_record.back_face.mode == BackFaceMode::Black {
+                let emitted = match hit_record.mat.scatter(ray, &hit_record, sampler) {
+                    Some((None, emitted)) if hit_record.back_face.emit && linked(&scene.objects[index].link, prev) => emitted * emission_weight(scene, light_tree, index, prev),
+                    _ => Vec3::new(0.0, 0.0, 0.0),
+                };
+
+                return lambda.upsample(emitted) * lambda.upsample(transmittance);
+            }
+
+            sampler.start_bounce_dimension(BSDF_DIMENSION, 4);
+
+            let scattered = hit_record.mat.scatter_with_pdf(ray, &hit_record, sampler);
 
             match scattered {
-                Some((sr, albedo)) => {
+                Some((sr, albedo, pdf)) => {
                     match sr {
                         Some(sr) => {
-                            let color = ray_color(&sr, &hit_world, depth - 1);
+                            let vertex = Vertex { pt: hit_record.pt, norm: hit_record.norm, name: hit_record.name, medium };
+                            let bsdf = |wi: &Vec3| surface_bsdf(ray, &hit_record, wi);
+                            let direct = direct_light(scene, light_tree, &vertex, bsdf, lambda, sampler) * lambda.upsample(transmittance);
+                            let wo = -ray.dir;
+                            let shading_reflect = sr.dir.dot(&hit_record.norm) * wo.dot(&hit_record.norm) > 0.0;
+                            let geometric_reflect = sr.dir.dot(&hit_record.geo_norm) * wo.dot(&hit_record.geo_norm) > 0.0;
 
-                            return color * albedo;
+                            if shading_reflect != geometric_reflect { return direct; }
+
+                            // Crossing a surface with an interior enters or leaves that medium.
+                            let medium = match (geometric_reflect, hit_record.mat.interior()) {
+                                (false, Some(interior)) => if hit_record.front_face { Some(interior) } else { None },
+                                _ => medium,
+                            };
+                            let kind = hit_record.mat.lobe(ray, &hit_record, &sr.dir);
+                            let bounce = Bounce { pt: hit_record.pt, norm: hit_record.norm, pdf, name: hit_record.name };
+                            let color = trace(&sr, scene, light_tree, depth - 1, lambda, kind, Some(bounce), medium, sampler);
+
+                            color * lambda.upsample(albedo) * lambda.upsample(transmittance) + direct
                         }
                         None => {
-                            return albedo;
+                            if !hit_record.front_face && !hit_record.back_face.emit { return lambda.upsample(Vec3::new(0.0, 0.0, 0.0)); }
+                            if !linked(&scene.objects[index].link, prev) { return lambda.upsample(Vec3::new(0.0, 0.0, 0.0)); }
+
+                            lambda.upsample(albedo) * lambda.upsample(transmittance) * emission_weight(scene, light_tree, index, prev)
                         }
                     }
                 }
                 None => {
-                    return Vec3::new(0.0, 0.0, 0.0);
+                    lambda.upsample(Vec3::new(0.0, 0.0, 0.0))
                 }
             }
         }
         None => {
-            let unit_dir = ray.dir.unit_vec();
-            let a = 0.5 * (unit_dir.y() + 1.0);
-
-            return Vec3::new(1.0, 1.0, 1.0) * (1.0 - a) + Vec3::new(0.5, 0.7, 1.0) * a;
+            lambda.upsample(background(scene, ray, prev)) * lambda.upsample(transmittance)
         }
     }
 }
 
 pub fn reflect(v: &Vec3, n: &Vec3) -> Vec3 {
-    *v - *n * v.dot(&n) * 2.0
+    *v - *n * v.dot(n) * 2.0
 }
 
 pub fn refract(uv: &Vec3, n: &Vec3, etai_over_etat: f64) -> Vec3 {
-    let cos_theta = -uv.dot(&n).min(1.0);
+    let cos_theta = -uv.dot(n).min(1.0);
     let r_out_perp = (*uv + *n * cos_theta) * etai_over_etat;
     let r_out_parallel = *n * -(1.0 - r_out_perp.len_sqr()).abs().sqrt();
 
@@ -130,4 +423,240 @@ pub fn reflectance(cos_theta: f64, refract_idx: f64) -> f64 {
     let r0_sqr = r0 * r0;
 
     r0_sqr + (1.0 - r0_sqr) * (1.0 - cos_theta).powi(5)
-}
\ No newline at end of file
+}
+
+pub fn fresnel_dielectric(cos_theta_i: f64, eta: f64) -> f64 {
+    let (cos_theta_i, eta) = if cos_theta_i < 0.0 { (-cos_theta_i, 1.0 / eta) } else { (cos_theta_i.min(1.0), eta) };
+    let sin2_theta_t = (1.0 - cos_theta_i * cos_theta_i) / (eta * eta);
+
+    if sin2_theta_t >= 1.0 { return 1.0; }
+
+    let cos_theta_t = (1.0 - sin2_theta_t).max(0.0).sqrt();
+    let r_parl = (eta * cos_theta_i - cos_theta_t) / (eta * cos_theta_i + cos_theta_t);
+    let r_perp = (cos_theta_i - eta * cos_theta_t) / (cos_theta_i + eta * cos_theta_t);
+
+    (r_parl * r_parl + r_perp * r_perp) / 2.0
+}
+
+pub fn radical_inverse(base: u64, mut i: u64) -> f64 {
+    let inv_base = 1.0 / (base as f64);
+    let mut inv = 1.0;
+    let mut value = 0.0;
+
+    while i > 0 {
+        inv *= inv_base;
+        value += ((i % base) as f64) * inv;
+        i /= base;
+    }
+
+    value.min(1.0 - f64::EPSILON)
+}
+
+pub fn onb(n: &Vec3) -> (Vec3, Vec3) {
+    let sign = 1.0_f64.copysign(n.z());
+    let a = -1.0 / (sign + n.z());
+    let b = n.x() * n.y() * a;
+
+    (
+        Vec3::new(1.0 + sign * n.x() * n.x() * a, sign * b, -sign * n.x()),
+        Vec3::new(b, sign + n.y() * n.y() * a, -n.y()),
+    )
+}
+
+#[cfg(test)]
+pub fn test_hit_record(mat: &Materials) -> HitRecord<'_> {
+    HitRecord {
+        pt: Vec3::new(0.0, 0.0, 0.0),
+        norm: Vec3::new(0.0, 0.0, 1.0),
+        geo_norm: Vec3::new(0.0, 0.0, 1.0),
+        dpdu: Vec3::new(1.0, 0.0, 0.0),
+        dpdv: Vec3::new(0.0, 1.0, 0.0),
+        t: 1.0,
+        u: 0.0,
+        v: 0.0,
+        front_face: true,
+        back_face: BackFace::default(),
+        mat,
+        name: "",
+    }
+}
+
+#[cfg(test)]
+pub fn furnace(mat: &Materials, samples: usize) -> Vec3 {
+    let ray = Ray::new(Vec3::new(0.3, 0.2, 1.0), Vec3::new(-0.3, -0.2, -1.0));
+    let hit_record = test_hit_record(mat);
+    let mut albedo = Vec3::new(0.0, 0.0, 0.0);
+    let mut sampler = PixelSampler::new(Samplers::default(), 0, (0, 0), 0);
+
+    for _ in 0..samples {
+        if let Some((Some(_), attenuation)) = mat.scatter(&ray, &hit_record, &mut sampler) {
+            albedo = albedo + attenuation;
+        }
+    }
+
+    albedo / (samples as f64)
+}
+
+#[test]
+fn test_direct_light() {
+    let mut scene = Scene::new(vec![
+        Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, Materials::Lambertian(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)))),
+    ]);
+    scene.lights.push(Lights::Point(PointLight::new(Vec3::new(0.0, 2.0, 0.0), Vec3::new(4.0, 4.0, 4.0))));
+    let mut sampler = PixelSampler::new(Samplers::default(), 0, (0, 0), 0);
+
+    let ray = Ray::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+    let color = ray_color(&ray, &scene, 1, &Rgb, &mut sampler);
+
+    assert_approx_eq!(color.x(), 0.5 / std::f64::consts::PI, 1e-6);
+
+    scene.objects.push(Sphere::new(Vec3::new(0.0, 1.0, 0.0), 0.5, Materials::Lambertian(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)))));
+
+    let shadowed = ray_color(&Ray::new(Vec3::new(2.0, 1.0, 0.0), Vec3::new(-2.0, -1.0, 0.0)), &scene, 1, &Rgb, &mut sampler);
+
+    assert_approx_eq!(shadowed.x(), 0.0);
+}
+
+#[test]
+fn test_spectral_direct_light() {
+    let albedo = Vec3::new(0.8, 0.1, 0.1);
+    let radiance = Vec3::new(0.1, 0.8, 0.1);
+    let mut scene = Scene::new(vec![
+        Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, Materials::Lambertian(Lambertian::new(albedo))),
+    ]);
+    scene.lights.push(Lights::Point(PointLight::new(Vec3::new(0.0, 2.0, 0.0), radiance * 4.0)));
+    let mut sampler = PixelSampler::new(Samplers::default(), 0, (0, 0), 0);
+    let lambda = SampledWavelengths::sample_visible(0.3);
+
+    let ray = Ray::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+    let color = lambda.to_xyz(&ray_color(&ray, &scene, 1, &lambda, &mut sampler));
+    let expected = lambda.to_xyz(&(lambda.upsample(albedo / std::f64::consts::PI) * lambda.upsample(radiance)));
+    let rgb_product = lambda.to_xyz(&lambda.upsample(albedo * radiance / std::f64::consts::PI));
+
+    assert_approx_eq!(color.x(), expected.x(), 1e-9);
+    assert_approx_eq!(color.y(), expected.y(), 1e-9);
+    assert_approx_eq!(color.z(), expected.z(), 1e-9);
+    assert!((color.y() - rgb_product.y()).abs() > 1e-3);
+}
+
+#[test]
+fn test_shading_normal_leak() {
+    let white = Materials::Lambertian(Lambertian::new(Vec3::new(1.0, 1.0, 1.0)));
+    let mut hit = test_hit_record(&white);
+    let ray = Ray::new(Vec3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0));
+
+    hit.norm = Vec3::new(0.6, 0.0, 0.8);
+
+    assert!(surface_bsdf(&ray, &hit, &Vec3::new(0.0, 0.0, 1.0)).is_some());
+    assert!(surface_bsdf(&ray, &hit, &Vec3::new(0.96, 0.0, -0.28)).is_none());
+}
+
+#[test]
+fn test_medium_direct_light() {
+    let mut scene = Scene::new(vec![]);
+    scene.lights.push(Lights::Point(PointLight::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(1.0, 1.0, 1.0) * 4.0 * std::f64::consts::PI)));
+    let light_tree = scene.light_tree();
+    let medium = HomogeneousMedium::new(Vec3::new(1.0, 1.0, 1.0), Vec3::new(1.0, 1.0, 1.0), 0.0);
+    let mut sampler = PixelSampler::new(Samplers::default(), 0, (0, 0), 0);
+    let ray = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+    let n = 20000;
+    let mut color = 0.0;
+
+    for _ in 0..n {
+        color += trace(&ray, &scene, &light_tree, 1, &Rgb, RayKind::Camera, None, Some(medium), &mut sampler).x() / (n as f64);
+    }
+
+    let dt = 1e-3;
+    let expected: f64 = (0..30000).map(|i| {
+        let t = (i as f64 + 0.5) * dt;
+        let r = (t * t + 1.0).sqrt();
+
+        (-t - r).exp() / (r * r) * dt
+    }).sum();
+
+    assert_approx_eq!(color, expected, 0.01);
+}
+
+#[test]
+fn test_directional_lights_sample_independently() {
+    let mut scene = Scene::new(vec![]);
+    scene.lights.push(Lights::Directional(DirectionalLight::new(Vec3::new(0.0, -1.0, 0.0), Vec3::new(1.0, 1.0, 1.0), 30.0)));
+    scene.lights.push(Lights::Directional(DirectionalLight::new(Vec3::new(0.0, -1.0, 0.0), Vec3::new(1.0, 1.0, 1.0), 30.0)));
+    let light_tree = scene.light_tree();
+    let vertex = Vertex { pt: Vec3::new(0.0, 0.0, 0.0), norm: Vec3::new(0.0, 1.0, 0.0), name: "", medium: None };
+    let mut sampler = PixelSampler::new(Samplers::default(), 0, (0, 0), 0);
+    let directions = std::cell::RefCell::new(vec![]);
+
+    sampler.start_bounce();
+    direct_light(&scene, &light_tree, &vertex, |wi: &Vec3| { directions.borrow_mut().push(*wi); None }, &Rgb, &mut sampler);
+
+    let directions = directions.into_inner();
+
+    assert_eq!(directions.len(), 2);
+    assert!((directions[0] - directions[1]).len() > 1e-6);
+}
+
+#[test]
+fn test_visibility_and_light_linking() {
+    let gray = Materials::Lambertian(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)));
+    let hidden = Visibility { camera: false, ..Visibility::default() };
+    let mut scene = Scene::new(vec![
+        Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, gray.clone()).with_name("ground"),
+        Sphere::new(Vec3::new(0.0, 1.0, 0.0), 0.5, gray).with_name("blocker").with_visibility(hidden),
+    ]);
+    let light = Lights::Point(PointLight::new(Vec3::new(0.0, 2.0, 0.0), Vec3::new(4.0, 4.0, 4.0)));
+    let ray = Ray::new(Vec3::new(0.0, 3.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+    let mut sampler = PixelSampler::new(Samplers::default(), 0, (0, 0), 0);
+
+    scene.lights = vec![light.clone()];
+    assert_approx_eq!(ray_color(&ray, &scene, 1, &Rgb, &mut sampler).x(), 0.0);
+
+    scene.lights = vec![light.clone().with_link(LightLink { shadow_exclude: vec!["blocker".to_string()], ..LightLink::default() })];
+    assert_approx_eq!(ray_color(&ray, &scene, 1, &Rgb, &mut sampler).x(), 0.5 / std::f64::consts::PI, 1e-6);
+
+    scene.lights = vec![light.with_link(LightLink { include: vec!["blocker".to_string()], shadow_exclude: vec!["blocker".to_string()], ..LightLink::default() })];
+    assert_approx_eq!(ray_color(&ray, &scene, 1, &Rgb, &mut sampler).x(), 0.0);
+}
+
+#[test]
+fn test_emitter_light_linking() {
+    let gray = Materials::Lambertian(Lambertian::new(Vec3::new(0.5, 0.5, 0.5)));
+    let light = Materials::DiffuseLight(DiffuseLight::new(Vec3::new(4.0, 4.0, 4.0)));
+    let excluded = LightLink { exclude: vec!["ground".to_string()], ..LightLink::default() };
+    let ground = Sphere::new(Vec3::new(0.0, -1000.0, 0.0), 1000.0, gray).with_name("ground");
+    let emitter = Sphere::new(Vec3::new(0.0, 2.0, 0.0), 0.25, light);
+    let ray = Ray::new(Vec3::new(1.0, 3.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+    let mut sampler = PixelSampler::new(Samplers::default(), 0, (0, 0), 0);
+
+    let dark = Sky::new(SkyParams { intensity: 0.0, ..SkyParams::default() });
+    let lit = Scene::new(vec![ground.clone(), emitter.clone()]).with_sky(dark.clone());
+    let unlit = Scene::new(vec![ground.clone(), emitter.with_link(excluded.clone())]).with_sky(dark);
+
+    assert!(ray_color(&ray, &lit, 2, &Rgb, &mut sampler).x() > 0.0);
+    assert_approx_eq!(ray_color(&ray, &unlit, 2, &Rgb, &mut sampler).x(), 0.0);
+
+    let sunlit = Scene::new(vec![ground.clone()]).with_sky(Sky::new(SkyParams::default()));
+    let shaded = Scene::new(vec![ground]).with_sky(Sky::new(SkyParams { link: excluded, ..SkyParams::default() }));
+
+    assert!(ray_color(&ray, &sunlit, 2, &Rgb, &mut sampler).x() > 0.0);
+    assert_approx_eq!(ray_color(&ray, &shaded, 2, &Rgb, &mut sampler).x(), 0.0);
+}
+
+#[test]
+fn test_back_face_emission() {
+    let light = Materials::DiffuseLight(DiffuseLight::new(Vec3::new(2.0, 2.0, 2.0)));
+    let ray = Ray::new(Vec3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.0));
+    let mut sampler = PixelSampler::new(Samplers::default(), 0, (0, 0), 0);
+
+    for (mode, emit, expected) in [
+        (BackFaceMode::Shade, true, 2.0),
+        (BackFaceMode::Shade, false, 0.0),
+        (BackFaceMode::Black, true, 2.0),
+        (BackFaceMode::Black, false, 0.0),
+    ] {
+        let sphere = Sphere::new(Vec3::new(0.0, 0.0, 0.0), 1.0, light.clone()).with_back_face(BackFace::new(mode, emit));
+        let color = ray_color(&ray, &Scene::new(vec![sphere]), 1, &Rgb, &mut sampler);
+
+        assert_approx_eq!(color.x(), expected);
+    }
+}
@@ -1,11 +1,14 @@
 use std::f64;
-use rand::Rng;
 use std::ops::{ Add, Sub, Mul, Div, Neg };
+use serde::{ Serialize, Deserialize };
+
+use crate::sampler::PixelSampler;
 
 #[cfg(test)]
 use assert_approx_eq::assert_approx_eq;
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(from = "[f64; 3]", into = "[f64; 3]")]
 pub struct Vec3 {
     x: f64,
     y: f64,
@@ -63,32 +66,33 @@ impl Vec3 {
         )
     }
 
-    pub fn random(min: f64, max: f64) -> Vec3 {
-        let mut rng = rand::thread_rng();
-
+    pub fn random(min: f64, max: f64, sampler: &mut PixelSampler) -> Vec3 {
         Vec3::new(
-            rng.gen_range(min..max),
-            rng.gen_range(min..max),
-            rng.gen_range(min..max),
+            min + (max - min) * sampler.get_1d(),
+            min + (max - min) * sampler.get_1d(),
+            min + (max - min) * sampler.get_1d(),
         )
     }
 
-    pub fn random_in_unit_sphere() -> Vec3 {
-        loop {
-            let p = Vec3::random(-1.0, 1.0);
+    pub fn random_in_unit_sphere(sampler: &mut PixelSampler) -> Vec3 {
+        let r = sampler.get_1d().cbrt();
 
-            if p.len_sqr() < 1.0 { return p; }
-        }
+        Vec3::random_unit_vec(sampler) * r
     }
 
-    pub fn random_unit_vec() -> Vec3 {
-        Vec3::random_in_unit_sphere().unit_vec()
+    pub fn random_unit_vec(sampler: &mut PixelSampler) -> Vec3 {
+        let (u, v) = sampler.get_2d();
+        let z = 1.0 - 2.0 * u;
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let phi = 2.0 * std::f64::consts::PI * v;
+
+        Vec3::new(r * phi.cos(), r * phi.sin(), z)
     }
 
-    pub fn random_on_hemisphere(norm: &Vec3) -> Vec3 {
-        let on_unit_sphere = Vec3::random_unit_vec();
+    pub fn random_on_hemisphere(norm: &Vec3, sampler: &mut PixelSampler) -> Vec3 {
+        let on_unit_sphere = Vec3::random_unit_vec(sampler);
 
-        if on_unit_sphere.dot(&norm) > 0.0 { return on_unit_sphere; }
+        if on_unit_sphere.dot(norm) > 0.0 { return on_unit_sphere; }
         
         -on_unit_sphere
     }
@@ -100,6 +104,18 @@ impl Vec3 {
     }
 }
 
+impl From<[f64; 3]> for Vec3 {
+    fn from(v: [f64; 3]) -> Vec3 {
+        Vec3::new(v[0], v[1], v[2])
+    }
+}
+
+impl From<Vec3> for [f64; 3] {
+    fn from(vec: Vec3) -> [f64; 3] {
+        [vec.x, vec.y, vec.z]
+    }
+}
+
 impl Add for Vec3 {
     type Output = Vec3;
 